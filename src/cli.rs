@@ -0,0 +1,992 @@
+use std::{collections::VecDeque, error::Error, io, path::Path, str::FromStr, time::Duration};
+use itertools::Itertools;
+use switch_selection::{solver::{benchmark::{BenchmarkParameters, GeneratorParameters}, benchmark_results::ResultsFormat, errors::{GraphError, SolverError, UsageError}, registry::SolverKind}, tree_decomposition::TreeDecompositionParameters, visualisation::VisualisationFormat};
+
+
+
+
+
+// Exit codes of the program
+pub const EXIT_SUCCESS: i32 = 0;
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_USAGE_ERROR: i32 = 2;
+pub const EXIT_INVALID_INPUT: i32 = 3;
+pub const EXIT_INFEASIBLE: i32 = 4;
+pub const EXIT_BACKEND_UNAVAILABLE: i32 = 5;
+pub const EXIT_TREE_DECOMPOSITION_FAILED: i32 = 6;
+pub const EXIT_IO_ERROR: i32 = 7;
+pub const EXIT_RESOURCE_LIMIT: i32 = 8;
+pub const EXIT_TIMEOUT: i32 = 9;
+
+
+
+// Errors the program can stop with
+pub trait ProgramError: Error {
+    fn exit_code(&self) -> i32;
+
+    // Substations the error concerns, listed after the error message
+    fn vertex_ids(&self) -> &[usize] {
+        &[]
+    }
+}
+
+// GraphError::ProgramError
+impl ProgramError for GraphError {
+    fn exit_code(&self) -> i32 {
+        match self {
+            GraphError::InvalidTopology { .. } | GraphError::MissingAttribute { .. } | GraphError::InvalidFormat { .. } => EXIT_INVALID_INPUT,
+            // A solution that fails the check is an infeasible configuration
+            GraphError::InvalidConfiguration { .. } => EXIT_INFEASIBLE,
+            GraphError::Io { .. } => EXIT_IO_ERROR,
+        }
+    }
+
+    fn vertex_ids(&self) -> &[usize] {
+        GraphError::vertex_ids(self)
+    }
+}
+
+// SolverError::ProgramError
+impl ProgramError for SolverError {
+    fn exit_code(&self) -> i32 {
+        match self {
+            SolverError::Infeasible { .. } => EXIT_INFEASIBLE,
+            SolverError::ResourceLimit { .. } => EXIT_RESOURCE_LIMIT,
+            SolverError::BackendUnavailable { .. } => EXIT_BACKEND_UNAVAILABLE,
+            SolverError::TreeDecompositionFailed { .. } => EXIT_TREE_DECOMPOSITION_FAILED,
+            SolverError::Io { .. } => EXIT_IO_ERROR,
+            SolverError::Timeout { .. } => EXIT_TIMEOUT,
+            SolverError::InvalidOptions { .. } => EXIT_USAGE_ERROR,
+            SolverError::BackendFailed { .. } | SolverError::InconsistentResults { .. } => EXIT_FAILURE,
+        }
+    }
+
+    fn vertex_ids(&self) -> &[usize] {
+        SolverError::vertex_ids(self)
+    }
+}
+
+// io::Error::ProgramError
+impl ProgramError for io::Error {
+    fn exit_code(&self) -> i32 {
+        EXIT_IO_ERROR
+    }
+}
+
+// Error message followed by the messages of its sources and by the substations
+// it concerns
+pub fn error_report<E: ProgramError>(error: &E) -> String {
+    let mut answer = error.to_string();
+    let mut source = error.source();
+    while let Some(value) = source {
+        answer += &format!(" {}", value);
+        source = value.source();
+    }
+    if !error.vertex_ids().is_empty() {
+        answer += &format!(" Substations concerned: {}.", error.vertex_ids().iter().join(", "));
+    }
+    answer
+}
+
+
+
+pub const HELP_STRING: &str =
+"switch-selection
+
+A tool to solve a variation of the SwitchSelection problem described in our  PSCC  paper.  See  our
+repository https://github.com/EINS-TUDa/PSCC2024-SwitchSelection for a detailed user guide.
+
+USAGE
+    switch-selection <COMMAND> [<OPTIONS>]
+    switch-selection help [<COMMAND>]
+
+COMMANDS
+    solve                    Solve an instance of the SwitchSelection problem.
+    benchmark                Solve randomly generated instances and  measure  the  performance  of
+                             the solvers: use it to reproduce the results from our paper.
+    generate                 Generate a random distribution grid and save it into a GNBS file.
+    verify                   Check that a solution saved in a GNBS file is feasible.
+    kernel                   Save a DG-kernel of a distribution grid into a file.
+    td                       Compute a tree decomposition of the DG-kernel of a distribution grid.
+    convert                  Convert a distribution grid into a different file format.
+    help                     Print this message or the help message of the given command.
+
+Run 'switch-selection help <COMMAND>' to learn about the options of each command.
+
+EXIT CODES
+    0                        Success.
+    1                        Other failure, e.g. CPLEX has reported an error or the solvers of  a
+                             benchmark disagree.
+    2                        The command line or the options of the solver are invalid.
+    3                        The input file is invalid, e.g. the grid is not connected.
+    4                        The problem instance is infeasible, or the configuration checked by
+                             'verify' is not feasible.
+    5                        The solver is not available, e.g. CPLEX can't be loaded.
+    6                        The tree decomposition can't be computed or imported.
+    7                        A file can't be read or written.
+    8                        The solver refuses the instance because of the memory budget or its
+                             size limit.
+    9                        The solver has exceeded the time limit before it found a solution.";
+
+pub const SOLVE_HELP_STRING: &str =
+"switch-selection solve
+
+Solve an instance of the SwitchSelection problem.
+
+USAGE
+    switch-selection solve [<OPTIONS>]
+
+OPTIONS
+    (-i|--input) PATH        Set the path to the input file in GNBS format.
+                             Default value: input.gnbs
+    (-o|--output) PATH       Set the path to the output file in GNBS format. If  the  file  doesn't
+                             exist, it'll  be  created  automatically.  If  the  file  exists,  its
+                             contents will be rewritten.
+                             Default value: output.gnbs
+    (-s|--solver) SOLVER     Set a solver to solve the problem instance with. Possible  values  for
+                             SOLVER:
+                                    o  TreeDecompositionSolver  -  solve  the  problem  using   the
+                                            dynamic programming approach described in our paper.
+                                    o  NiceTreeDecompositionSolver  -  the  same  approach  over  a
+                                            nice tree decomposition: memos of introduce, forget and
+                                            join bags are derived from the memos of their children.
+                                    o  CPLEXSolver  -  solve the problem in  its  MILP  formulation
+                                            using CPLEX (requires CPLEX to be installed).
+                                    o  BruteForceSolver  -  try  all  combinations of tap positions
+                                            and  all  cuts of each line; only for instances with at
+                                            most 5 primary substations.
+                             Default value: TreeDecompositionSolver
+    --dgkernel [PATH]        Save a DG-kernel of the input graph into a GNBS file. If PATH  is  not
+                             given, value 'dgkernel.gnbs' is assumed.
+    --timeit [N [M]]         Solve the problem instance N times and report the mean, median,  min,
+                             max and standard deviation of the solution time. The first  M  runs
+                             are used as a warm-up and are not measured. The solution  obtained
+                             in the last run is saved.
+                             Default values if only --timeit is given: N = 100, M = 10
+    --profile                Report the peak resident memory of the process and, for
+                             TreeDecompositionSolver, the number of entries in the memo of  each
+                             bag, the largest number of entries allocated for a table of each bag
+                             and the total number of memo entries processed.
+    --memory-budget SIZE     Refuse to solve the problem instance if the solver estimates that  it
+                             would take more than SIZE bytes of memory. SIZE may end with K, M  or
+                             G, e.g. 4G. Only TreeDecompositionSolver estimates its memory usage:
+                             its memos can take up to 21^(width + 1) entries per bag.
+    --fallback SOLVER        Solve the problem instance with SOLVER if the  solver  set  with  -s
+                             refuses to solve it because of --memory-budget.
+    --time-limit SECONDS     Give up solving after SECONDS seconds of wall-clock time, not counting
+                             the computation of the tree decomposition (see --td-time-limit).  With
+                             --timeit the limit applies to each run.  Only  BruteForceSolver  saves
+                             the best solution found so far, which may not  be  optimal;  the  tree
+                             decomposition solvers and CPLEXSolver stop with exit code 9.
+    --threads N              Set the number of worker threads of TreeDecompositionSolver  and
+                             NiceTreeDecompositionSolver. A bag is processed as soon as the memos of
+                             all its children are computed, and idle workers help to  process  the
+                             tables of large bags. With N = 1, the bags are processed one after
+                             another on the main thread without spawning threads, e.g.  for
+                             debugging or on single-core machines. The solution doesn't depend on N.
+                             Default value: the number of logical CPUs
+    --td-algorithm ALGORITHM Set the algorithm that computes the tree decomposition of the DG-kernel.
+                             Possible values for ALGORITHM:
+                                    o  exact  -  an optimal tree decomposition computed by  the
+                                            TamakiPid solver.
+                                    o  min-degree  -  a tree decomposition given by the  minimum
+                                            degree elimination ordering.
+                                    o  min-fill  -  a tree decomposition given by  the  minimum
+                                            fill-in elimination ordering.
+                             Default value: exact
+    --td-time-limit SECONDS  Stop the exact algorithm after SECONDS seconds and use the tree
+                             decomposition of the lowest width found by the heuristics instead.
+                             The heuristics are also used if the exact algorithm fails.
+    --td-input PATH          Use the tree decomposition of the DG-kernel saved in PACE .td format
+                             instead of computing one, e.g. one computed by an external tool  for
+                             the DG-kernel saved with 'switch-selection td --gr'. The vertices  of
+                             the DG-kernel are numbered from 1 in the ascending order of their IDs.
+                             The decomposition is checked to cover all vertices and edges and  to
+                             satisfy the running intersection property.
+    --export-grid PATH       Save a drawing of the solution into a DOT (.dot or .gv)  or  GraphML
+                             (.graphml) file: each substation is coloured by the primary substation
+                             that feeds it and opened switches are drawn as dashed red edges.
+    --export-kernel PATH     Save a drawing of the DG-kernel with the tap  positions  of  the
+                             solution into a DOT (.dot or .gv) or GraphML (.graphml) file.
+    --export-td PATH         Save a drawing of the tree decomposition used by TreeDecompositionSolver
+                             into a DOT (.dot or .gv) or GraphML (.graphml) file. Each bag is labelled
+                             with its vertices and the number of entries in its memo.
+
+EXAMPLES
+    switch-selection solve
+        Equivalent to 'switch-selection solve -i input.gnbs -o output.gnbs -s TreeDecompositionSolver'.
+    switch-selection solve -i ./Graphs/example1.gnbs -s CPLEXSolver
+        Solve the SwitchSelection instance given by ./Graphs/example1.gnbs  with  CPLEX,  save  the
+        optimal solution into output.gnbs.
+    switch-selection solve --memory-budget 8G --fallback CPLEXSolver
+        Solve the SwitchSelection instance given by input.gnbs with TreeDecompositionSolver if it
+        fits into 8 GiB of memory and with CPLEX otherwise.
+    switch-selection solve -o 123.gnbs --dgkernel dgk.gnbs
+        Solve the SwitchSelection instance given by input.gnbs with  TreeDecompositionSolver,  save
+        the optimal solution into 123.gnbs and save the DG-kernel into dgk.gnbs.
+    switch-selection solve --export-grid grid.dot --export-td td.graphml
+        Solve input.gnbs, draw the solution for Graphviz and the  tree  decomposition  with  the
+        sizes of the memos for Gephi.";
+
+pub const BENCHMARK_HELP_STRING: &str =
+"switch-selection benchmark
+
+Solve randomly generated instances of the SwitchSelection problem with the given solvers and measure
+their performance. When launched without options, reproduces the results from our PSCC paper. Solvers
+whose backends are not available on this machine (e.g. CPLEXSolver without CPLEX) are skipped.
+
+Each instance is generated by sampling a random DG-kernel on the primary substations and then
+replacing each of its edges with a random number of feeders. RANGE is either 'A..B' or  a  single
+value 'A'. Integer ranges include both bounds, real ranges include the lower bound only.  Powers
+and impedances are given in per unit.
+
+USAGE
+    switch-selection benchmark [<OPTIONS>]
+
+OPTIONS
+    --treewidth RANGE        Set the treewidths of the generated DG-kernels.
+                             Default value: 2
+    --primary RANGE          Set the numbers of primary substations in a DG-kernel.
+                             Default value: 10..30
+    --samples N              Set the number of feasible samples for each treewidth  and  number  of
+                             primary substations.
+                             Default value: 20
+    --repeat N               Set the number of times each sample is solved by each solver.
+                             Default value: 1
+    --ignore N               Set the number of first runs of each solver that are not measured.
+                             Must be less than the value of --repeat.
+                             Default value: 0
+    --topology TOPOLOGY      Set the topology of the DG-kernels. Possible values for TOPOLOGY:
+                                    o  k-tree  -  a random partial k-tree with k = treewidth.
+                                    o  urban  -  a planar mesh of city blocks with treewidth rows.
+                                    o  rural  -  a long chain of primary substations with sparse
+                                            ties that span at most treewidth hops.
+                             Default value: k-tree
+    --ties P                 Set the probability of each tie of a rural chain.
+                             Default value: 0.2
+    --feeders RANGE          Set the number of feeders that replace each edge of the DG-kernel.
+                             Default value: 2..5
+    --secondary RANGE        Set the number of secondary substations on each feeder.
+                             Default value: 5..10
+    --feeder-model MODEL     Set how impedances of the edges are chosen. Possible values for MODEL:
+                                    o  uniform  -  r and x are sampled from --r and --x.
+                                    o  cables  -  each feeder has a random 20 kV cable type  and
+                                            each edge has a random length sampled from --length.
+                             Default value: uniform
+    --r RANGE                Set the range of resistances of the edges.
+                             Default value: 0.01..0.05
+    --x RANGE                Set the range of reactances of the edges.
+                             Default value: 0.01..0.05
+    --length RANGE           Set the range of lengths of the edges in km.
+                             Default value: 0.2..1.5
+    --load-model MODEL       Set how loads of secondary substations are chosen. Possible values for
+                             MODEL:
+                                    o  uniform  -  p and q are sampled from --p and --q.
+                                    o  profiles  -  each secondary substation is  residential  or
+                                            commercial, and its peak load is scaled  by  the
+                                            standard load profile at a random hour of the day.
+                             Default value: uniform
+    --p RANGE                Set the range of active powers at secondary substations.
+                             Default value: -0.5..0.5
+    --q RANGE                Set the range of reactive powers at secondary substations.
+                             Default value: -0.5..0.5
+    --commercial-share P     Set the probability that a secondary substation is commercial.
+                             Default value: 0.3
+    --residential-peak RANGE Set the range of peak active powers of residential substations.
+                             Default value: 0.02..0.08
+    --commercial-peak RANGE  Set the range of peak active powers of commercial substations.
+                             Default value: 0.05..0.2
+    --power-factor RANGE     Set the range of power factors of secondary substations.
+                             Default value: 0.9..0.98
+    --seed SEED              Set the seed of the pseudo-random number generator.
+                             Default value: 13374
+    --solvers LIST           Set a comma-separated list of solvers to measure. See 'switch-selection
+                             help solve' for the possible solvers.
+                             Default value: TreeDecompositionSolver,CPLEXSolver
+    --check-objectives       Check that all solvers find the same optimal value for  each  sample,
+                             or all find it infeasible, and stop with an error otherwise.
+    (-o|--output) PATH       Save a record for each generated sample (including  infeasible  ones)
+                             into a CSV or JSON file. The format is deduced from the extension  of
+                             the file: either .csv or .json. Each record contains  the  seed,  the
+                             treewidth, the numbers of primary and secondary  substations,  the
+                             width of the tree decomposition, the final and peak sizes of the memos,
+                             the number of memo entries processed and the time, the optimal value,
+                             the feasibility verdict and the peak resident memory of each solver.
+    --corpus DIR             Save each generated sample (including infeasible ones) into DIR as  a
+                             GNBS file named s<SEED>_tw<K>_n<N>_sample<I>.gnbs and  list  all
+                             samples with their parameters, feasibility labels and optimal values
+                             in DIR/manifest.csv. The directory is created if it doesn't exist.
+
+EXAMPLES
+    switch-selection benchmark --treewidth 2..4 --primary 10..15 --samples 5 --seed 42
+        Measure the performance of the solvers on 5 samples for each treewidth from 2 to 4 and each
+        number of primary substations from 10 to 15.
+    switch-selection benchmark --solvers TreeDecompositionSolver -o results.csv
+        Reproduce the results from our PSCC paper for TreeDecompositionSolver only  and  save  them
+        into results.csv.
+    switch-selection benchmark --treewidth 3 --primary 12 --samples 50 --corpus corpus
+        Save 50 feasible samples and all infeasible samples generated  along  the  way  into  the
+        corpus directory.";
+
+pub const GENERATE_HELP_STRING: &str =
+"switch-selection generate
+
+Generate a random distribution grid and save it into a GNBS file, e.g. to reuse it as an input  for
+'switch-selection solve'. The generated grid is not guaranteed to be feasible.
+
+USAGE
+    switch-selection generate [<OPTIONS>]
+
+OPTIONS
+    --treewidth K            Set the treewidth of the DG-kernel.
+                             Default value: 2
+    --primary N              Set the number of primary substations.
+                             Default value: 10
+    --seed SEED              Set the seed of the pseudo-random number generator.
+                             Default value: 13374
+    (-o|--output) PATH       Set the path to the output file in GNBS format.
+                             Default value: input.gnbs
+
+    All options that describe the generator in 'switch-selection help benchmark' (--topology, --ties,
+    --feeders, --secondary, --feeder-model, --r, --x, --length, --load-model, --p,  --q,
+    --commercial-share, --residential-peak, --commercial-peak, --power-factor) are accepted as well.
+
+EXAMPLES
+    switch-selection generate --topology urban --feeder-model cables --load-model profiles -o urban.gnbs
+        Generate an urban grid with 10 primary substations, realistic cables and  loads  and  save
+        it into urban.gnbs.";
+
+pub const VERIFY_HELP_STRING: &str =
+"switch-selection verify
+
+Check that a solution saved in a GNBS file by 'switch-selection solve' is feasible: each line  must
+have exactly one opened switch and all squared voltages must lie in [0.81, 1.21].
+
+USAGE
+    switch-selection verify [<OPTIONS>]
+
+OPTIONS
+    (-i|--input) PATH        Set the path to the solution file in GNBS format.
+                             Default value: output.gnbs";
+
+pub const KERNEL_HELP_STRING: &str =
+"switch-selection kernel
+
+Save a DG-kernel of a distribution grid into a file.
+
+USAGE
+    switch-selection kernel [<OPTIONS>]
+
+OPTIONS
+    (-i|--input) PATH        Set the path to the input file in GNBS format.
+                             Default value: input.gnbs
+    (-o|--output) PATH       Set the path to the output file. The format is deduced from its
+                             extension: gnbs, gr (PACE graph format), dot or gv (a drawing  for
+                             Graphviz) or graphml (a drawing for Gephi).
+                             Default value: dgkernel.gnbs";
+
+pub const TD_HELP_STRING: &str =
+"switch-selection td
+
+Compute a tree decomposition of the DG-kernel of a distribution grid and print its bags.
+
+USAGE
+    switch-selection td [<OPTIONS>]
+
+OPTIONS
+    (-i|--input) PATH        Set the path to the input file in GNBS format.
+                             Default value: input.gnbs
+    --nice                   Convert the tree decomposition into a nice one and print the kind  of
+                             each bag: leaf, introduce, forget or join.
+    --td-algorithm ALGORITHM Set the algorithm that computes the tree decomposition of the DG-kernel.
+                             Possible values for ALGORITHM:
+                                    o  exact  -  an optimal tree decomposition computed by  the
+                                            TamakiPid solver.
+                                    o  min-degree  -  a tree decomposition given by the  minimum
+                                            degree elimination ordering.
+                                    o  min-fill  -  a tree decomposition given by  the  minimum
+                                            fill-in elimination ordering.
+                             Default value: exact
+    --td-time-limit SECONDS  Stop the exact algorithm after SECONDS seconds and use the tree
+                             decomposition of the lowest width found by the heuristics instead.
+                             The heuristics are also used if the exact algorithm fails.
+    --td-input PATH          Use the tree decomposition of the DG-kernel saved in PACE .td format
+                             instead of computing one, e.g. one computed by an external tool  for
+                             the DG-kernel saved with 'switch-selection td --gr'. The vertices  of
+                             the DG-kernel are numbered from 1 in the ascending order of their IDs.
+                             The decomposition is checked to cover all vertices and edges and  to
+                             satisfy the running intersection property.
+    (-o|--output) PATH       Save the tree decomposition into a file in PACE .td format.
+    --gr PATH                Save the DG-kernel into a file in PACE .gr format with the same numbering
+                             of vertices as in the saved tree decomposition.
+    --export PATH            Save a drawing of the tree decomposition into a DOT (.dot or .gv)  or
+                             GraphML (.graphml) file. Each bag points to its children and  is
+                             labelled with its vertices and, with --nice, its kind.
+
+EXAMPLES
+    switch-selection td --gr kernel.gr -o kernel.td
+        Save the DG-kernel of input.gnbs and its tree decomposition in PACE formats.
+    switch-selection solve --td-input kernel.td
+        Solve input.gnbs with TreeDecompositionSolver reusing the saved tree decomposition.";
+
+pub const CONVERT_HELP_STRING: &str =
+"switch-selection convert
+
+Convert a distribution grid into a different file format. The format of the output file is  deduced
+from its extension. Supported extensions:
+       o  gnbs  -  GNBS format.
+       o  gr  -  PACE graph format (the attributes of the grid are dropped).
+       o  dot, gv  -  a drawing for Graphviz: each substation is  coloured  by  the  primary
+               substation that feeds it and opened switches are drawn as dashed red edges.
+       o  graphml  -  the same drawing for Gephi, with the attributes of the grid.
+
+USAGE
+    switch-selection convert [<OPTIONS>]
+
+OPTIONS
+    (-i|--input) PATH        Set the path to the input file in GNBS format.
+                             Default value: input.gnbs
+    (-o|--output) PATH       Set the path to the output file.
+                             Default value: output.gr";
+
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileFormat {
+    GNBS,
+    PACEGraph,
+    Drawing(VisualisationFormat),
+}
+
+// FileFormat::FileFormat
+impl FileFormat {
+    pub fn from_path(path: &str) -> Result<Self, UsageError> {
+        match Path::new(path).extension().and_then(|x| x.to_str()) {
+            Some("gnbs") => Ok(FileFormat::GNBS),
+            Some("gr") => Ok(FileFormat::PACEGraph),
+            Some("dot") | Some("gv") | Some("graphml") => Ok(FileFormat::Drawing(VisualisationFormat::from_path(path)?)),
+            _ => Err(UsageError::from_string(format!("Can't deduce a supported file format from the extension of {}.", path))),
+        }
+    }
+}
+
+
+
+#[derive(Clone, Debug)]
+pub struct SolveOptions {
+    pub input_path: String,
+    pub output_path: String,
+    pub solver: SolverKind,
+    pub dg_kernel_path: Option<String>,
+    pub timeit: Option<(usize, usize)>,
+    pub profile: bool,
+    // In bytes
+    pub memory_budget: Option<usize>,
+    pub fallback_solver: Option<SolverKind>,
+    // Wall-clock time limit of each run of the solver
+    pub time_limit: Option<Duration>,
+    // Number of worker threads of the tree decomposition solvers
+    pub threads: Option<usize>,
+    pub td_parameters: TreeDecompositionParameters,
+    // Paths to save the drawings of the solution, the DG-kernel  and  the  tree
+    // decomposition into
+    pub grid_drawing: Option<(String, VisualisationFormat)>,
+    pub dg_kernel_drawing: Option<(String, VisualisationFormat)>,
+    pub td_drawing: Option<(String, VisualisationFormat)>,
+}
+
+#[derive(Clone, Debug)]
+pub struct GenerateOptions {
+    pub treewidth: usize,
+    pub primary_substation_count: usize,
+    pub generator: GeneratorParameters,
+    pub seed: u64,
+    pub output_path: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct VerifyOptions {
+    pub input_path: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct KernelOptions {
+    pub input_path: String,
+    pub output_path: String,
+    pub output_format: FileFormat,
+}
+
+#[derive(Clone, Debug)]
+pub struct TreeDecompositionOptions {
+    pub input_path: String,
+    pub nice: bool,
+    pub td_parameters: TreeDecompositionParameters,
+    // Paths to save the tree decomposition (PACE .td) and the DG-kernel  (PACE
+    // .gr) into
+    pub output_path: Option<String>,
+    pub graph_output_path: Option<String>,
+    pub drawing: Option<(String, VisualisationFormat)>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ConvertOptions {
+    pub input_path: String,
+    pub output_path: String,
+    pub output_format: FileFormat,
+}
+
+
+
+#[derive(Clone, Debug)]
+pub enum Command {
+    Help(&'static str),
+    Solve(SolveOptions),
+    Benchmark(BenchmarkParameters),
+    Generate(GenerateOptions),
+    Verify(VerifyOptions),
+    Kernel(KernelOptions),
+    TreeDecomposition(TreeDecompositionOptions),
+    Convert(ConvertOptions),
+}
+
+
+
+struct ArgumentStream {
+    arguments: VecDeque<String>,
+}
+
+// ArgumentStream::ArgumentStream
+impl ArgumentStream {
+    fn next_flag(&mut self) -> Option<String> {
+        self.arguments.pop_front()
+    }
+
+    fn expect_value(&mut self, flag: &str) -> Result<String, UsageError> {
+        match self.arguments.pop_front() {
+            Some(value) => Ok(value),
+            None => Err(UsageError::from_string(format!("Option {} expects a value.", flag))),
+        }
+    }
+
+    fn expect_parsed<T: FromStr>(&mut self, flag: &str) -> Result<T, UsageError> {
+        let value = self.expect_value(flag)?;
+        parse_value(flag, &value)
+    }
+
+    // Ranges are given either as 'A..B' or as a single value 'A' that  stands
+    // for 'A..A'.
+    fn expect_range<T: FromStr + PartialOrd + Copy>(&mut self, flag: &str) -> Result<(T, T), UsageError> {
+        let value = self.expect_value(flag)?;
+        let range: (T, T) = match value.split_once("..") {
+            Some((lower_bound, upper_bound)) => (parse_value(flag, lower_bound)?, parse_value(flag, upper_bound)?),
+            None => {
+                let bound = parse_value(flag, &value)?;
+                (bound, bound)
+            },
+        };
+        if range.0 > range.1 {
+            return Err(UsageError::from_string(format!("The lower bound of {} exceeds its upper bound.", flag)));
+        }
+        Ok(range)
+    }
+
+    // Lists are given as comma-separated values
+    fn expect_list<T: FromStr + PartialEq>(&mut self, flag: &str) -> Result<Vec<T>, UsageError> {
+        let value = self.expect_value(flag)?;
+        let mut answer: Vec<T> = Vec::new();
+        for item in value.split(',').filter(|x| !x.is_empty()) {
+            let parsed = parse_value(flag, item)?;
+            if !answer.contains(&parsed) {
+                answer.push(parsed);
+            }
+        }
+        Ok(answer)
+    }
+
+    fn next_value_if<T: FromStr>(&mut self) -> Option<T> {
+        let parsed = self.arguments.front().and_then(|x| x.parse().ok());
+        if parsed.is_some() {
+            self.arguments.pop_front();
+        }
+        parsed
+    }
+
+    fn expect_drawing_path(&mut self, flag: &str) -> Result<(String, VisualisationFormat), UsageError> {
+        let path = self.expect_value(flag)?;
+        let format = VisualisationFormat::from_path(&path)?;
+        Ok((path, format))
+    }
+
+    fn next_path_if_not_flag(&mut self) -> Option<String> {
+        match self.arguments.front() {
+            Some(value) if !value.starts_with('-') => self.arguments.pop_front(),
+            _ => None,
+        }
+    }
+}
+
+
+
+fn parse_value<T: FromStr>(flag: &str, value: &str) -> Result<T, UsageError> {
+    match value.parse() {
+        Ok(value) => Ok(value),
+        Err(_) => Err(UsageError::from_string(format!("Invalid value '{}' for option {}.", value, flag))),
+    }
+}
+
+// Time limits are given in seconds and must be positive and  small  enough
+// to be represented as a Duration
+fn parse_time_limit(flag: &str, seconds: f64) -> Result<Duration, UsageError> {
+    match Duration::try_from_secs_f64(seconds) {
+        Ok(value) if !value.is_zero() => Ok(value),
+        _ => Err(UsageError::from_string(format!("The time limit of {} must be a positive number of seconds below 1.8e19.", flag))),
+    }
+}
+
+// Memory sizes are given in bytes, optionally followed by one of the  binary
+// suffixes K, M or G, e.g. '512M'.
+fn parse_memory_size(flag: &str, value: &str) -> Result<usize, UsageError> {
+    let (number, multiplier) = match value.chars().last() {
+        Some('K') | Some('k') => (&value[..value.len() - 1], 1usize << 10),
+        Some('M') | Some('m') => (&value[..value.len() - 1], 1usize << 20),
+        Some('G') | Some('g') => (&value[..value.len() - 1], 1usize << 30),
+        _ => (value, 1),
+    };
+    let number: usize = parse_value(flag, number)?;
+    match number.checked_mul(multiplier) {
+        Some(value) => Ok(value),
+        None => Err(UsageError::from_string(format!("Invalid value '{}' for option {}.", value, flag))),
+    }
+}
+
+#[inline]
+fn unknown_option(command: &str, flag: &str) -> UsageError {
+    UsageError::from_string(format!("Unknown option {} for command '{}'.", flag, command))
+}
+
+fn parse_solve(mut arguments: ArgumentStream) -> Result<Command, UsageError> {
+    let mut options = SolveOptions {
+        input_path: "input.gnbs".to_string(),
+        output_path: "output.gnbs".to_string(),
+        solver: SolverKind::TreeDecomposition,
+        dg_kernel_path: None,
+        timeit: None,
+        profile: false,
+        memory_budget: None,
+        fallback_solver: None,
+        time_limit: None,
+        threads: None,
+        td_parameters: TreeDecompositionParameters::default(),
+        grid_drawing: None,
+        dg_kernel_drawing: None,
+        td_drawing: None,
+    };
+    while let Some(flag) = arguments.next_flag() {
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(Command::Help(SOLVE_HELP_STRING)),
+            "-i" | "--input" => options.input_path = arguments.expect_value(&flag)?,
+            "-o" | "--output" => options.output_path = arguments.expect_value(&flag)?,
+            "-s" | "--solver" => options.solver = arguments.expect_parsed(&flag)?,
+            "--profile" => options.profile = true,
+            "--memory-budget" => {
+                let value = arguments.expect_value(&flag)?;
+                options.memory_budget = Some(parse_memory_size(&flag, &value)?);
+            },
+            "--fallback" => options.fallback_solver = Some(arguments.expect_parsed(&flag)?),
+            "--time-limit" => {
+                let seconds: f64 = arguments.expect_parsed(&flag)?;
+                options.time_limit = Some(parse_time_limit(&flag, seconds)?);
+            },
+            "--threads" => {
+                let threads: usize = arguments.expect_parsed(&flag)?;
+                if threads == 0 {
+                    return Err(UsageError::from_str("The number of threads must be positive."));
+                }
+                options.threads = Some(threads);
+            },
+            "--export-grid" => options.grid_drawing = Some(arguments.expect_drawing_path(&flag)?),
+            "--export-kernel" => options.dg_kernel_drawing = Some(arguments.expect_drawing_path(&flag)?),
+            "--export-td" => options.td_drawing = Some(arguments.expect_drawing_path(&flag)?),
+            "--dgkernel" => options.dg_kernel_path = Some(arguments.next_path_if_not_flag().unwrap_or("dgkernel.gnbs".to_string())),
+            "--timeit" => {
+                let repeat: usize = arguments.next_value_if().unwrap_or(100);
+                let ignore: usize = arguments.next_value_if().unwrap_or(if repeat > 10 { 10 } else { 0 });
+                if ignore >= repeat {
+                    return Err(UsageError::from_str("The number of warm-up runs for --timeit must be less than the number of runs."));
+                }
+                options.timeit = Some((repeat, ignore));
+            },
+            _ => if !parse_td_option(&flag, &mut arguments, &mut options.td_parameters)? {
+                return Err(unknown_option("solve", &flag));
+            },
+        }
+    }
+    if options.fallback_solver.is_some() && options.memory_budget.is_none() {
+        return Err(UsageError::from_str("Option --fallback requires --memory-budget."));
+    }
+    if options.fallback_solver == Some(options.solver) {
+        return Err(UsageError::from_str("The fallback solver must differ from the main solver."));
+    }
+    Ok(Command::Solve(options))
+}
+
+// Parses an option of the computation of tree decompositions. Returns false
+// if the flag is not such an option.
+fn parse_td_option(flag: &str, arguments: &mut ArgumentStream, parameters: &mut TreeDecompositionParameters) -> Result<bool, UsageError> {
+    match flag {
+        "--td-algorithm" => parameters.algorithm = arguments.expect_parsed(flag)?,
+        "--td-time-limit" => {
+            let seconds: f64 = arguments.expect_parsed(flag)?;
+            parameters.time_limit = Some(parse_time_limit(flag, seconds)?);
+        },
+        "--td-input" => parameters.input_path = Some(arguments.expect_value(flag)?),
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+// Parses an option of the generator of random distribution grids. Returns
+// false if the flag is not an option of the generator.
+fn parse_generator_option(flag: &str, arguments: &mut ArgumentStream, parameters: &mut GeneratorParameters) -> Result<bool, UsageError> {
+    match flag {
+        "--topology" => parameters.topology = arguments.expect_parsed(flag)?,
+        "--ties" => parameters.tie_probability = arguments.expect_parsed(flag)?,
+        "--feeders" => parameters.feeder_count = arguments.expect_range(flag)?,
+        "--secondary" => parameters.secondary_substation_count = arguments.expect_range(flag)?,
+        "--feeder-model" => parameters.feeder_model = arguments.expect_parsed(flag)?,
+        "--r" => parameters.r = arguments.expect_range(flag)?,
+        "--x" => parameters.x = arguments.expect_range(flag)?,
+        "--length" => parameters.edge_length = arguments.expect_range(flag)?,
+        "--load-model" => parameters.load_model = arguments.expect_parsed(flag)?,
+        "--p" => parameters.p = arguments.expect_range(flag)?,
+        "--q" => parameters.q = arguments.expect_range(flag)?,
+        "--commercial-share" => parameters.commercial_share = arguments.expect_parsed(flag)?,
+        "--residential-peak" => parameters.residential_peak = arguments.expect_range(flag)?,
+        "--commercial-peak" => parameters.commercial_peak = arguments.expect_range(flag)?,
+        "--power-factor" => parameters.power_factor = arguments.expect_range(flag)?,
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+fn validate_generator_parameters(parameters: &GeneratorParameters) -> Result<(), UsageError> {
+    if parameters.feeder_count.0 == 0 {
+        return Err(UsageError::from_str("The number of feeders per edge must be positive."));
+    }
+    if parameters.secondary_substation_count.0 == 0 {
+        return Err(UsageError::from_str("The number of secondary substations per feeder must be positive."));
+    }
+    for (flag, range) in [
+        ("--r", parameters.r),
+        ("--x", parameters.x),
+        ("--length", parameters.edge_length),
+        ("--p", parameters.p),
+        ("--q", parameters.q),
+        ("--residential-peak", parameters.residential_peak),
+        ("--commercial-peak", parameters.commercial_peak),
+        ("--power-factor", parameters.power_factor),
+    ] {
+        if range.0 >= range.1 {
+            return Err(UsageError::from_string(format!("The range of {} must have its lower bound less than its upper bound.", flag)));
+        }
+    }
+    for (flag, probability) in [("--ties", parameters.tie_probability), ("--commercial-share", parameters.commercial_share)] {
+        if !(0.0..=1.0).contains(&probability) {
+            return Err(UsageError::from_string(format!("The value of {} must be in [0, 1].", flag)));
+        }
+    }
+    if parameters.power_factor.0 <= 0.0 || parameters.power_factor.1 > 1.0 {
+        return Err(UsageError::from_str("The range of --power-factor must lie in (0, 1]."));
+    }
+    Ok(())
+}
+
+fn parse_benchmark(mut arguments: ArgumentStream) -> Result<Command, UsageError> {
+    let mut parameters = BenchmarkParameters::default();
+    while let Some(flag) = arguments.next_flag() {
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(Command::Help(BENCHMARK_HELP_STRING)),
+            "--treewidth" => parameters.treewidth = arguments.expect_range(&flag)?,
+            "--primary" => parameters.primary_substation_count = arguments.expect_range(&flag)?,
+            "--samples" => parameters.sample_count = arguments.expect_parsed(&flag)?,
+            "--repeat" => parameters.sample_repeat = arguments.expect_parsed(&flag)?,
+            "--ignore" => parameters.sample_ignore = arguments.expect_parsed(&flag)?,
+            "--seed" => parameters.seed = arguments.expect_parsed(&flag)?,
+            "--solvers" => parameters.solvers = arguments.expect_list(&flag)?,
+            "--check-objectives" => parameters.check_objectives = true,
+            "--corpus" => parameters.corpus = Some(arguments.expect_value(&flag)?),
+            "-o" | "--output" => {
+                let path = arguments.expect_value(&flag)?;
+                let format = ResultsFormat::from_path(&path)?;
+                parameters.results = Some((path, format));
+            },
+            _ => if !parse_generator_option(&flag, &mut arguments, &mut parameters.generator)? {
+                return Err(unknown_option("benchmark", &flag));
+            },
+        }
+    }
+    if parameters.treewidth.0 == 0 {
+        return Err(UsageError::from_str("The treewidth must be positive."));
+    }
+    if parameters.primary_substation_count.1 <= parameters.treewidth.0 {
+        return Err(UsageError::from_str("The number of primary substations must be greater than the treewidth."));
+    }
+    validate_generator_parameters(&parameters.generator)?;
+    if parameters.sample_count == 0 {
+        return Err(UsageError::from_str("The number of samples must be positive."));
+    }
+    if parameters.solvers.is_empty() {
+        return Err(UsageError::from_str("At least one solver must be given to --solvers."));
+    }
+    if parameters.sample_ignore >= parameters.sample_repeat {
+        return Err(UsageError::from_str("The value of --ignore must be less than the value of --repeat."));
+    }
+    Ok(Command::Benchmark(parameters))
+}
+
+fn parse_generate(mut arguments: ArgumentStream) -> Result<Command, UsageError> {
+    let mut options = GenerateOptions {
+        treewidth: 2,
+        primary_substation_count: 10,
+        generator: GeneratorParameters::default(),
+        seed: 13374,
+        output_path: "input.gnbs".to_string(),
+    };
+    while let Some(flag) = arguments.next_flag() {
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(Command::Help(GENERATE_HELP_STRING)),
+            "--treewidth" => options.treewidth = arguments.expect_parsed(&flag)?,
+            "--primary" => options.primary_substation_count = arguments.expect_parsed(&flag)?,
+            "--seed" => options.seed = arguments.expect_parsed(&flag)?,
+            "-o" | "--output" => options.output_path = arguments.expect_value(&flag)?,
+            _ => if !parse_generator_option(&flag, &mut arguments, &mut options.generator)? {
+                return Err(unknown_option("generate", &flag));
+            },
+        }
+    }
+    if options.treewidth == 0 {
+        return Err(UsageError::from_str("The treewidth must be positive."));
+    }
+    if options.primary_substation_count <= options.treewidth {
+        return Err(UsageError::from_str("The number of primary substations must be greater than the treewidth."));
+    }
+    validate_generator_parameters(&options.generator)?;
+    Ok(Command::Generate(options))
+}
+
+fn parse_verify(mut arguments: ArgumentStream) -> Result<Command, UsageError> {
+    let mut options = VerifyOptions { input_path: "output.gnbs".to_string() };
+    while let Some(flag) = arguments.next_flag() {
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(Command::Help(VERIFY_HELP_STRING)),
+            "-i" | "--input" => options.input_path = arguments.expect_value(&flag)?,
+            _ => return Err(unknown_option("verify", &flag)),
+        }
+    }
+    Ok(Command::Verify(options))
+}
+
+fn parse_kernel(mut arguments: ArgumentStream) -> Result<Command, UsageError> {
+    let mut options = KernelOptions { input_path: "input.gnbs".to_string(), output_path: "dgkernel.gnbs".to_string(), output_format: FileFormat::GNBS };
+    while let Some(flag) = arguments.next_flag() {
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(Command::Help(KERNEL_HELP_STRING)),
+            "-i" | "--input" => options.input_path = arguments.expect_value(&flag)?,
+            "-o" | "--output" => options.output_path = arguments.expect_value(&flag)?,
+            _ => return Err(unknown_option("kernel", &flag)),
+        }
+    }
+    options.output_format = FileFormat::from_path(&options.output_path)?;
+    Ok(Command::Kernel(options))
+}
+
+fn parse_td(mut arguments: ArgumentStream) -> Result<Command, UsageError> {
+    let mut options = TreeDecompositionOptions {
+        input_path: "input.gnbs".to_string(),
+        nice: false,
+        td_parameters: TreeDecompositionParameters::default(),
+        output_path: None,
+        graph_output_path: None,
+        drawing: None,
+    };
+    while let Some(flag) = arguments.next_flag() {
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(Command::Help(TD_HELP_STRING)),
+            "-i" | "--input" => options.input_path = arguments.expect_value(&flag)?,
+            "--nice" => options.nice = true,
+            "-o" | "--output" => options.output_path = Some(arguments.expect_value(&flag)?),
+            "--gr" => options.graph_output_path = Some(arguments.expect_value(&flag)?),
+            "--export" => options.drawing = Some(arguments.expect_drawing_path(&flag)?),
+            _ => if !parse_td_option(&flag, &mut arguments, &mut options.td_parameters)? {
+                return Err(unknown_option("td", &flag));
+            },
+        }
+    }
+    Ok(Command::TreeDecomposition(options))
+}
+
+fn parse_convert(mut arguments: ArgumentStream) -> Result<Command, UsageError> {
+    let mut options = ConvertOptions { input_path: "input.gnbs".to_string(), output_path: "output.gr".to_string(), output_format: FileFormat::PACEGraph };
+    while let Some(flag) = arguments.next_flag() {
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(Command::Help(CONVERT_HELP_STRING)),
+            "-i" | "--input" => options.input_path = arguments.expect_value(&flag)?,
+            "-o" | "--output" => options.output_path = arguments.expect_value(&flag)?,
+            _ => return Err(unknown_option("convert", &flag)),
+        }
+    }
+    options.output_format = FileFormat::from_path(&options.output_path)?;
+    Ok(Command::Convert(options))
+}
+
+fn help_for(command: Option<&str>) -> Result<Command, UsageError> {
+    match command {
+        None => Ok(Command::Help(HELP_STRING)),
+        Some("solve") => Ok(Command::Help(SOLVE_HELP_STRING)),
+        Some("benchmark") => Ok(Command::Help(BENCHMARK_HELP_STRING)),
+        Some("generate") => Ok(Command::Help(GENERATE_HELP_STRING)),
+        Some("verify") => Ok(Command::Help(VERIFY_HELP_STRING)),
+        Some("kernel") => Ok(Command::Help(KERNEL_HELP_STRING)),
+        Some("td") => Ok(Command::Help(TD_HELP_STRING)),
+        Some("convert") => Ok(Command::Help(CONVERT_HELP_STRING)),
+        Some(value) => Err(UsageError::from_string(format!("Unknown command '{}'.", value))),
+    }
+}
+
+
+
+pub fn parse_arguments<I: Iterator<Item = String>>(arguments: I) -> Result<Command, UsageError> {
+    let mut arguments = ArgumentStream { arguments: arguments.collect() };
+    let command = match arguments.next_flag() {
+        Some(value) => value,
+        None => return Ok(Command::Help(HELP_STRING)),
+    };
+    match command.as_str() {
+        "-h" | "--help" => Ok(Command::Help(HELP_STRING)),
+        "help" => help_for(arguments.next_flag().as_deref()),
+        "solve" => parse_solve(arguments),
+        "benchmark" => parse_benchmark(arguments),
+        "generate" => parse_generate(arguments),
+        "verify" => parse_verify(arguments),
+        "kernel" => parse_kernel(arguments),
+        "td" => parse_td(arguments),
+        "convert" => parse_convert(arguments),
+        _ => Err(UsageError::from_string(format!("Unknown command '{}'. Run 'switch-selection help' to see the list of commands.", command))),
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::parse_time_limit;
+
+    #[test]
+    fn time_limits_out_of_range_are_rejected() {
+        assert_eq!(parse_time_limit("--time-limit", 2.5).unwrap(), Duration::from_millis(2500));
+        for seconds in [0.0, -1.0, 1e300, f64::INFINITY, f64::NAN] {
+            assert!(parse_time_limit("--time-limit", seconds).is_err(), "seconds = {}", seconds);
+        }
+    }
+}
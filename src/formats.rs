@@ -0,0 +1,142 @@
+use std::{collections::HashMap, fs, io};
+use crabnets::{io::IO, BasicImmutableGraph, Graph, ImmutableGraphContainer};
+use itertools::Itertools;
+use crate::{solver::errors::GraphError, switch_selection_instance::SwitchSelectionGraph, tree_decomposition::{TreeDecomposition, TreeDecompositionAlgorithm}};
+
+
+
+
+
+pub fn read_gnbs(path: &str) -> Result<SwitchSelectionGraph, GraphError> {
+    Graph::from_file(path).map_err(|x| GraphError::Io { description: format!("Failed to read {}.", path), source: Box::from(x.to_string()) })
+}
+
+pub fn write_gnbs(graph: &SwitchSelectionGraph, path: &str) -> Result<(), GraphError> {
+    graph.into_file(path).map_err(|x| GraphError::Io { description: format!("Failed to write {}.", path), source: Box::from(x.to_string()) })
+}
+
+// PACE formats require vertices to be numbered from 1  to  n.  The  vertices
+// of the graph are numbered in the ascending order of their IDs.
+pub fn pace_vertex_numbering(graph: &SwitchSelectionGraph) -> HashMap<usize, usize> {
+    graph.iter_v().sorted().enumerate().map(|(i, x)| (x, i + 1)).collect()
+}
+
+pub fn pace_graph_string(graph: &SwitchSelectionGraph) -> String {
+    let numbering = pace_vertex_numbering(graph);
+    let mut answer = format!("p tw {} {}\n", graph.count_v(), graph.count_e());
+    for (id1, id2) in graph.iter_e().map(|x| (numbering[&x.id1], numbering[&x.id2])).sorted() {
+        answer += &format!("{} {}\n", id1, id2);
+    }
+    answer
+}
+
+pub fn write_pace_graph(graph: &SwitchSelectionGraph, path: &str) -> io::Result<()> {
+    fs::write(path, pace_graph_string(graph))
+}
+
+// Vertices of the tree decomposition are numbered with pace_vertex_numbering
+// of the graph it decomposes, and its bags are numbered from 1 in the ascending
+// order of their IDs.
+pub fn pace_tree_decomposition_string(td: &TreeDecomposition, graph: &SwitchSelectionGraph) -> String {
+    let numbering = pace_vertex_numbering(graph);
+    let bag_numbering: HashMap<usize, usize> = td.iter_v().sorted().enumerate().map(|(i, x)| (x, i + 1)).collect();
+    let mut answer = format!("s td {} {} {}\n", td.count_v(), td.max_bag_size, graph.count_v());
+    for bag_id in td.iter_v().sorted() {
+        answer += &format!("b {}", bag_numbering[&bag_id]);
+        for vertex in td.v_attrs(&bag_id).unwrap().vertices.iter() {
+            answer += &format!(" {}", numbering[vertex]);
+        }
+        answer += "\n";
+    }
+    for (id1, id2) in td.iter_e().map(|x| (bag_numbering[&x.id1], bag_numbering[&x.id2])).sorted() {
+        answer += &format!("{} {}\n", id1, id2);
+    }
+    answer
+}
+
+pub fn write_pace_tree_decomposition(td: &TreeDecomposition, graph: &SwitchSelectionGraph, path: &str) -> io::Result<()> {
+    fs::write(path, pace_tree_decomposition_string(td, graph))
+}
+
+// Reads a tree decomposition of graph in PACE .td format and checks that it's
+// indeed a tree decomposition of graph.
+pub fn read_pace_tree_decomposition(path: &str, graph: &SwitchSelectionGraph) -> Result<TreeDecomposition, GraphError> {
+    let contents = match fs::read_to_string(path) {
+        Ok(value) => value,
+        Err(error) => return Err(GraphError::Io { description: format!("Failed to read {}.", path), source: Box::new(error) }),
+    };
+    let td = parse_pace_tree_decomposition(&contents, graph).map_err(|x| GraphError::InvalidFormat { description: format!("Invalid tree decomposition in {}. {}", path, x) })?;
+    td.validate(graph).map_err(|x| match x {
+        GraphError::InvalidTopology { description, vertex_ids } => GraphError::InvalidTopology { description: format!("Invalid tree decomposition in {}. {}", path, description), vertex_ids },
+        x => x,
+    })?;
+    Ok(td)
+}
+
+pub fn parse_pace_tree_decomposition(contents: &str, graph: &SwitchSelectionGraph) -> Result<TreeDecomposition, GraphError> {
+    // Vertex i of the file is the i-th vertex of the graph in the ascending order of IDs
+    let vertex_ids = graph.iter_v().sorted().collect_vec();
+    let mut header: Option<(usize, usize)> = None;
+    let mut bags: Vec<Option<Vec<usize>>> = Vec::new();
+    let mut edges: Vec<(usize, usize)> = Vec::new();
+    for (line_i, line) in contents.lines().enumerate().map(|(i, x)| (i + 1, x.trim())) {
+        let tokens = line.split_whitespace().collect_vec();
+        // Solution line: s td <# bags> <max bag size> <# vertices>
+        // Bag line: b <bag> <vertex> ... <vertex>
+        // Edge line: <bag> <bag>
+        let (kind, numbers) = match tokens.first() {
+            None | Some(&"c") => continue,
+            Some(&"s") if tokens.get(1) == Some(&"td") => ("s", &tokens[2..]),
+            Some(&"b") => ("b", &tokens[1..]),
+            _ => ("e", &tokens[..]),
+        };
+        let numbers = numbers
+            .iter()
+            .map(|x| x.parse::<usize>())
+            .collect::<Result<Vec<usize>, _>>()
+            .map_err(|_| GraphError::InvalidFormat { description: format!("Line {}: expected non-negative integers.", line_i) })?;
+        if kind == "s" {
+            if header.is_some() || numbers.len() != 3 {
+                return Err(GraphError::InvalidFormat { description: format!("Line {}: invalid solution line.", line_i) });
+            }
+            if numbers[2] != vertex_ids.len() {
+                return Err(GraphError::InvalidFormat { description: format!("Line {}: the graph has {} vertices, not {}.", line_i, vertex_ids.len(), numbers[2]) });
+            }
+            header = Some((numbers[0], numbers[1]));
+            bags = vec![None; numbers[0]];
+            continue;
+        }
+        let (bag_count, max_bag_size) = match header {
+            Some(value) => value,
+            None => return Err(GraphError::InvalidFormat { description: format!("Line {}: the solution line must come first.", line_i) }),
+        };
+        if kind == "b" {
+            if numbers.is_empty() || numbers[0] == 0 || numbers[0] > bag_count || bags[numbers[0] - 1].is_some() {
+                return Err(GraphError::InvalidFormat { description: format!("Line {}: invalid or repeated bag number.", line_i) });
+            }
+            if numbers.len() - 1 > max_bag_size {
+                return Err(GraphError::InvalidFormat { description: format!("Line {}: the bag has more than {} vertices.", line_i, max_bag_size) });
+            }
+            let mut vertices = Vec::with_capacity(numbers.len() - 1);
+            for &vertex in numbers[1..].iter() {
+                if vertex == 0 || vertex > vertex_ids.len() {
+                    return Err(GraphError::InvalidFormat { description: format!("Line {}: vertex {} doesn't exist.", line_i, vertex) });
+                }
+                vertices.push(vertex_ids[vertex - 1]);
+            }
+            bags[numbers[0] - 1] = Some(vertices);
+        } else {
+            if numbers.len() != 2 || numbers.iter().any(|&x| x == 0 || x > bag_count) {
+                return Err(GraphError::InvalidFormat { description: format!("Line {}: invalid edge of the tree decomposition.", line_i) });
+            }
+            edges.push((numbers[0] - 1, numbers[1] - 1));
+        }
+    }
+    if header.is_none() {
+        return Err(GraphError::InvalidFormat { description: "The solution line is missing.".to_string() });
+    }
+    if let Some(bag_i) = bags.iter().position(|x| x.is_none()) {
+        return Err(GraphError::InvalidFormat { description: format!("Bag {} is missing.", bag_i + 1) });
+    }
+    TreeDecomposition::from_bags(bags.into_iter().map(|x| x.unwrap()).collect(), &edges, TreeDecompositionAlgorithm::Imported)
+}
@@ -0,0 +1,142 @@
+pub mod formats;
+pub mod solver;
+pub mod switch_selection_instance;
+pub mod tree_decomposition;
+pub mod visualisation;
+
+use solver::benchmark::timeit;
+pub use solver::{
+    base_solver::{BaseSolver, SolverLimits, SolverStatistics, TapValue},
+    benchmark::TimingStatistics,
+    brute_force_solver::BruteForceSolver,
+    cplex_solver::CPLEXSolver,
+    errors::{GraphError, SolverError},
+    registry::SolverKind,
+    solution::{LineCut, ObjectiveBreakdown, Solution},
+    tree_decomposition_solver::{NiceTreeDecompositionSolver, TreeDecompositionSolver},
+};
+pub use switch_selection_instance::{DGEdgeAttributes, DGVertexAttributes, SwitchSelectionGraph, SwitchSelectionInstance};
+pub use tree_decomposition::{TreeDecomposition, TreeDecompositionAlgorithm, TreeDecompositionParameters};
+
+
+
+
+
+// Options of solve
+#[derive(Clone, Debug)]
+pub struct SolverOptions {
+    pub solver: SolverKind,
+    pub td_parameters: TreeDecompositionParameters,
+    // Number of worker threads of the tree decomposition solvers. All CPUs are
+    // used by default, and 0 is treated as 1.
+    pub threads: Option<usize>,
+    // In bytes
+    pub memory_budget: Option<usize>,
+    // Solver to use instead of solver if the latter refuses the problem instance
+    // because of the memory budget or its limits (SolverError::ResourceLimit)
+    pub fallback: Option<SolverKind>,
+    // The problem instance is solved repeat times, and the first ignore runs are
+    // left out of the timing. repeat must be greater than ignore, otherwise
+    // solve fails with SolverError::InvalidOptions.
+    pub repeat: usize,
+    pub ignore: usize,
+    // Time limit and cancellation token of each run
+    pub limits: SolverLimits,
+    // The exact tree decomposition algorithm can't be stopped, so with a  time
+    // limit in td_parameters it runs on a thread that is left running in the
+    // background once the limit is exceeded. The same holds for CPLEXSolver
+    // with any limits. solve refuses such options unless this is true, which is
+    // only reasonable if the process exits soon after.
+    pub allow_detached_td_thread: bool,
+}
+
+// SolverOptions::Default
+impl Default for SolverOptions {
+    fn default() -> Self {
+        SolverOptions {
+            solver: SolverKind::TreeDecomposition,
+            td_parameters: TreeDecompositionParameters::default(),
+            threads: None,
+            memory_budget: None,
+            fallback: None,
+            repeat: 1,
+            ignore: 0,
+            limits: SolverLimits::default(),
+            allow_detached_td_thread: false,
+        }
+    }
+}
+
+
+
+fn solve_with<S: BaseSolver>(mut solver: S, solver_kind: SolverKind, options: &SolverOptions) -> Result<Solution, SolverError> {
+    solver.check_memory_budget(&solver_kind.to_string(), options.memory_budget)?;
+    solver.set_limits(options.limits.clone())?;
+    let timing = timeit(&mut solver, options.repeat, options.ignore)?;
+    let mut solution = solver.try_get_solution()?;
+    solution.timing = Some(timing);
+    solution.tree_decomposition = solver.get_tree_decomposition().cloned();
+    Ok(solution)
+}
+
+// Solves the problem instance with the solver given in the options, or with
+// the fallback solver if the former refuses it because of its limits
+pub fn solve(instance: SwitchSelectionInstance, options: &SolverOptions) -> Result<Solution, SolverError> {
+    if options.repeat <= options.ignore {
+        return Err(SolverError::InvalidOptions { description: format!(
+            "{}. The number of runs ({}) must be greater than the number of ignored runs ({}).",
+            options.solver, options.repeat, options.ignore
+        ) });
+    }
+    let detaches_td_thread = options.td_parameters.algorithm == TreeDecompositionAlgorithm::Exact
+        && options.td_parameters.time_limit.is_some()
+        && options.td_parameters.input_path.is_none();
+    for solver_kind in [Some(options.solver), options.fallback].into_iter().flatten() {
+        if detaches_td_thread && !options.allow_detached_td_thread && matches!(solver_kind, SolverKind::TreeDecomposition | SolverKind::NiceTreeDecomposition) {
+            return Err(SolverError::InvalidOptions { description: format!(
+                "{}. The exact tree decomposition algorithm can't be stopped at its time limit. Use a heuristic algorithm, drop the time limit or set allow_detached_td_thread.",
+                solver_kind
+            ) });
+        }
+        if !options.limits.is_unlimited() && !options.allow_detached_td_thread && solver_kind == SolverKind::CPLEX {
+            return Err(SolverError::InvalidOptions { description: format!(
+                "{}. CPLEX can't be stopped at the limits. Drop the limits or set allow_detached_td_thread.",
+                solver_kind
+            ) });
+        }
+    }
+    match options.fallback {
+        Some(fallback) => match solve_with_kind(instance.clone(), options.solver, options) {
+            Err(SolverError::ResourceLimit { .. }) => solve_with_kind(instance, fallback, options),
+            result => result,
+        },
+        None => solve_with_kind(instance, options.solver, options),
+    }
+}
+
+// Solves the problem instance with the solver of the given kind
+fn solve_with_kind(instance: SwitchSelectionInstance, solver_kind: SolverKind, options: &SolverOptions) -> Result<Solution, SolverError> {
+    let solver_name = solver_kind.to_string();
+    match solver_kind {
+        SolverKind::TreeDecomposition => {
+            // Computing the tree decomposition may take long, so the instance is
+            // refused beforehand if no decomposition fits into the memory budget
+            TreeDecompositionSolver::check_memory_lower_bound(&instance, &solver_name, options.memory_budget)?;
+            let mut solver = TreeDecompositionSolver::new(instance, false, &options.td_parameters)?;
+            if let Some(threads) = options.threads {
+                solver.set_thread_count(threads);
+            }
+            solve_with(solver, solver_kind, options)
+        },
+        SolverKind::NiceTreeDecomposition => {
+            TreeDecompositionSolver::check_memory_lower_bound(&instance, &solver_name, options.memory_budget)?;
+            let mut solver = NiceTreeDecompositionSolver::new(instance, &options.td_parameters)?;
+            if let Some(threads) = options.threads {
+                solver.set_thread_count(threads);
+            }
+            solve_with(solver, solver_kind, options)
+        },
+        SolverKind::CPLEX => solve_with(CPLEXSolver::with_input(instance)?, solver_kind, options),
+        SolverKind::BruteForce => solve_with(BruteForceSolver::with_input(instance)?, solver_kind, options),
+    }
+}
@@ -1,22 +1,27 @@
-mod switch_selection_instance;
-mod tree_decomposition;
-mod solver;
+mod cli;
 
-use std::{env, process::exit, time::Instant};
-use crabnets::{io::IO, Graph};
-use switch_selection_instance::{SwitchSelectionInstance, SwitchSelectionGraph};
-use solver::{base_solver::BaseSolver, cplex_solver::CPLEXSolver, tree_decomposition_solver::TreeDecompositionSolver, benchmark::start_benchmark};
-use crate::solver::base_solver::TapValue;
+use std::{env, process::exit};
+use crabnets::{BasicImmutableGraph, ImmutableGraphContainer};
+use itertools::Itertools;
+use cli::*;
+use switch_selection::{
+    formats,
+    solve,
+    solver::benchmark::{generate_distribution_grid, peak_resident_memory, reset_peak_resident_memory, start_benchmark},
+    visualisation::Drawing,
+    Solution, SolverError, SolverKind, SolverLimits, SolverOptions, SolverStatistics, SwitchSelectionGraph, SwitchSelectionInstance, TapValue, TreeDecomposition,
+};
 
 
 
 
 
 macro_rules! pretty_panic {
-    ($message: expr) => {
+    ($error: expr) => {
         {
-            println!("{}", $message);
-            exit(1);
+            let error = $error;
+            eprintln!("{}", error_report(&error));
+            exit(error.exit_code());
         }
     };
 }
@@ -34,203 +39,174 @@ macro_rules! pretty_unwrap {
 
 
 
-fn main() {
-    const HELP_STRING: &str =
-"switch-selection
-
-A tool to solve a variation of the SwitchSelection problem described in our  PSCC  paper.  See  our
-repository https://github.com/EINS-TUDa/PSCC2024-SwitchSelection for a detailed user guide.
-
-USAGE
-    OPTION 1: switch-selection [...] (-h|--help) [...]
-    OPTION 2: switch-selection (-b|--benchmark)
-    OPTION 3: switch-selection <ARGUMENTS> [<OPTIONS>]
-
-    OPTION 1 prints this message. OPTION 2 launches benchmark: use it to reproduce the results from
-    our paper. Use OPTION 3 to solve a specific instance of the SwitchSelection problem.
-
-ARGUMENTS
-    (-i|--input) PATH        Set the path to the input file in GNBS format.
-                             Default value if this argument is omitted: -i input.gnbs
-    (-o|--output) PATH       Set the path to the output file in GNBS format. If  the  file  doesn't
-                             exist, it'll  be  created  automatically.  If  the  file  exists,  its
-                             contents will be rewritten.
-                             Default value if this argument is omitted: -o output.gnbs
-    (-s|--solver) SOLVER     Set a solver to solve the problem instance with. Possible  values  for
-                             SOLVER:
-                                    o  TreeDecompositionSolver  -  solve  the  problem  using   the
-                                            dynamic programming approach described in our paper.
-                                    o  CPLEXSolver  -  solve the problem in  its  MILP  formulation
-                                            using CPLEX (requires CPLEX to be installed).
-                             Default value if this argument is omitted: -s TreeDecompositionSolver
-
-OPTIONS
-    --dgkernel [PATH]        Save a DG-kernel of the input graph into a GNBS file. If PATH  is  not
-                             given, value 'dgkernel.gnbs' is assumed.
-
-EXAMPLES
-    switch-selection
-        Equivalent to 'switch-selection -i input.gnbs -o output.gnbs -s TreeDecompositionSolver'.
-    switch-selection -i ./Graphs/example1.gnbs -s CPLEXSolver
-        Solve the SwitchSelection instance given by ./Graphs/example1.gnbs  with  CPLEX,  save  the
-        optimal solution into output.gnbs.
-    switch-selection -o 123.gnbs --dgkernel dgk.gnbs
-        Solve the SwitchSelection instance given by input.gnbs with  TreeDecompositionSolver,  save
-        the optimal solution into 123.gnbs and save the DG-kernel into dgk.gnbs.";
-
-
-
-    let mut benchmark_mode: Option<bool> = None;
-    let mut input_path: String = "input.gnbs".to_string();
-    let mut output_path: String = "output.gnbs".to_string();
-    let mut solver_name: String = "TreeDecompositionSolver".to_string();
-    let mut dg_kernel_path: Option<String> = None;
-    let mut timeit: Option<(usize, usize)> = None;
-    let mut target_path: &mut String = &mut input_path;
-    // Command-line parser states
-    enum CLParserState {
-        ExpectParameter,
-        ExpectPath,
-        ExpectPathOrParameter,
-        ExpectSolver,
-        ExpectNumber1OrParameter,
-        ExpectNumber2,
-    }
+fn read_instance(input_path: &str) -> SwitchSelectionInstance {
+    let input: SwitchSelectionGraph = pretty_unwrap!(formats::read_gnbs(input_path));
+    pretty_unwrap!(SwitchSelectionInstance::new(input))
+}
 
+fn print_profile(statistics: &SolverStatistics, peak_memory: Option<u64>) {
+    match peak_memory {
+        Some(value) => println!("Peak resident memory = {} KiB.", value),
+        None => println!("Peak resident memory is not available on this platform."),
+    }
+    if let Some(width) = statistics.tree_decomposition_width {
+        println!("Tree decomposition width = {}, # memo entries processed = {}.", width, statistics.processed_memo_entries);
+        for (&(bag_id, entries), &(_, peak_entries)) in statistics.memo_sizes.iter().zip(statistics.peak_memo_sizes.iter()) {
+            println!("Bag {}: # memo entries = {}, # allocated entries = {}", bag_id, entries, peak_entries);
+        }
+    }
+}
 
+fn run_solver_of_kind(problem_instance: SwitchSelectionInstance, solver_kind: SolverKind, options: &SolveOptions) -> Result<(), SolverError> {
+    if options.profile {
+        reset_peak_resident_memory();
+    }
+    let (repeat, ignore) = options.timeit.unwrap_or((1, 0));
+    let solver_options = SolverOptions {
+        solver: solver_kind,
+        td_parameters: options.td_parameters.clone(),
+        threads: options.threads,
+        memory_budget: options.memory_budget,
+        // The fallback solver is run by the caller, which reports it
+        fallback: None,
+        repeat,
+        ignore,
+        limits: SolverLimits { time_limit: options.time_limit, cancellation_token: None },
+        // The process exits right after solving, which stops the thread
+        allow_detached_td_thread: true,
+    };
+    let mut grid = problem_instance.unwrap().clone();
+    let solution: Solution = solve(problem_instance, &solver_options)?;
+    let timing = solution.timing.as_ref().unwrap();
+    match options.timeit {
+        Some(_) => {
+            println!("{} solved the problem instance {} times after {} warm-up runs.", solver_kind, repeat - ignore, ignore);
+            println!("Timing: {}.", timing);
+        },
+        None => println!("{} solved the problem instance in {} s.", solver_kind, timing.times[0]),
+    }
+    if options.profile {
+        print_profile(&solution.statistics, peak_resident_memory());
+    }
+    if !solution.optimal {
+        println!("The time limit has been exceeded, the solution may not be optimal.");
+    }
+    println!("Objective value = {}.", solution.objective.value);
+    solution.render(&mut grid);
+    pretty_unwrap!(formats::write_gnbs(&grid, &options.output_path));
+    export_drawings(&solution, &grid, options);
+    Ok(())
+}
 
-    // Read command-line arguments
-    let mut state: CLParserState = CLParserState::ExpectParameter;
-    let mut arguments: env::Args = env::args();
-    arguments.next();
-    for argument in arguments {
-        match argument.as_str() {
-            "-b" | "--benchmark" => if benchmark_mode == Some(false) {
-                pretty_panic!(format!("You can't use {} together with any other parameters.", argument));
-            } else {
-                benchmark_mode = Some(true);
-            },
-            "-h" | "--help" => {
-                println!("{}", HELP_STRING);
-                exit(0);
-            },
-            "-i" | "--input" => match state {
-                CLParserState::ExpectParameter | CLParserState::ExpectPathOrParameter | CLParserState::ExpectNumber1OrParameter => {
-                    if benchmark_mode == Some(true) {
-                        pretty_panic!(format!("You can't use {} in the benchmark mode.", argument));
-                    }
-                    benchmark_mode = Some(false);
-                    target_path = &mut input_path;
-                    state = CLParserState::ExpectPath;
-                },
-                _ => pretty_panic!(format!("Unexpected command-line value {}.", argument)),
-            },
-            "-o" | "--output" => match state {
-                CLParserState::ExpectParameter | CLParserState::ExpectPathOrParameter | CLParserState::ExpectNumber1OrParameter => {
-                    if benchmark_mode == Some(true) {
-                        pretty_panic!(format!("You can't use {} in the benchmark mode.", argument));
-                    }
-                    benchmark_mode = Some(false);
-                    target_path = &mut output_path;
-                    state = CLParserState::ExpectPath;
-                },
-                _ => pretty_panic!(format!("Unexpected command-line value {}.", argument)),
-            },
-            "-s" | "--solver" => match state {
-                CLParserState::ExpectParameter | CLParserState::ExpectPathOrParameter | CLParserState::ExpectNumber1OrParameter => {
-                    if benchmark_mode == Some(true) {
-                        pretty_panic!(format!("You can't use {} in the benchmark mode.", argument));
-                    }
-                    benchmark_mode = Some(false);
-                    state = CLParserState::ExpectSolver;
-                },
-                _ => pretty_panic!(format!("Unexpected command-line value {}.", argument)),
-            },
-            "--dgkernel" => match state {
-                CLParserState::ExpectParameter | CLParserState::ExpectNumber1OrParameter => {
-                    if benchmark_mode == Some(true) {
-                        pretty_panic!(format!("You can't use {} in the benchmark mode.", argument));
-                    }
-                    benchmark_mode = Some(false);
-                    dg_kernel_path = Some("dgkernel.gnbs".to_string());
-                    state = CLParserState::ExpectPathOrParameter;
-                },
-                _ => pretty_panic!(format!("Unexpected command-line value {}.", argument)),
-            },
-            "--timeit" => match state {
-                CLParserState::ExpectParameter | CLParserState::ExpectPathOrParameter => {
-                    if benchmark_mode == Some(true) {
-                        pretty_panic!(format!("You can't use {} in the benchmark mode.", argument));
-                    }
-                    benchmark_mode = Some(false);
-                    timeit = Some((100, 10));
-                    state = CLParserState::ExpectNumber1OrParameter;
-                },
-                _ => pretty_panic!(format!("Unexpected command-line value {}.", argument)),
-            },
-            a => match state {
-                CLParserState::ExpectParameter => pretty_panic!(format!("Unknown command-line parameter {}.", a)),
-                CLParserState::ExpectPath => {
-                    *target_path = argument;
-                    state = CLParserState::ExpectParameter;
-                },
-                CLParserState::ExpectPathOrParameter => {
-                    dg_kernel_path = Some(a.to_string());
-                    state = CLParserState::ExpectParameter;
-                }
-                CLParserState::ExpectSolver => {
-                    match a {
-                        "TreeDecompositionSolver" | "CPLEXSolver" => solver_name = a.to_string(),
-                        _ => pretty_panic!(format!("Unknown solver {}.", a)),
-                    }
-                    state = CLParserState::ExpectParameter;
-                },
-                CLParserState::ExpectNumber1OrParameter => {
-                    timeit = Some((pretty_unwrap!(a.parse()), 0));
-                    state = CLParserState::ExpectNumber2;
-                },
-                CLParserState::ExpectNumber2 => {
-                    timeit = Some((timeit.unwrap().0, pretty_unwrap!(a.parse())));
-                    state = CLParserState::ExpectParameter;
-                },
-            },
-        }
+// grid is the grid of the problem instance with the solution rendered onto it
+fn export_drawings(solution: &Solution, grid: &SwitchSelectionGraph, options: &SolveOptions) {
+    if let Some((path, format)) = &options.grid_drawing {
+        pretty_unwrap!(Drawing::for_grid(grid).write(path, *format));
+    }
+    if let Some((path, format)) = &options.dg_kernel_drawing {
+        let dg_kernel: SwitchSelectionGraph = pretty_unwrap!(SwitchSelectionInstance::new(grid.clone())).dg_kernel_for_switch_selection();
+        pretty_unwrap!(Drawing::for_dg_kernel(&dg_kernel, grid).write(path, *format));
     }
-    match state {
-        CLParserState::ExpectParameter | CLParserState::ExpectPathOrParameter | CLParserState::ExpectNumber1OrParameter => (),
-        _ => pretty_panic!(format!("Unexpected end of command line.")),
+    if let Some((path, format)) = &options.td_drawing {
+        match &solution.tree_decomposition {
+            Some(td) => pretty_unwrap!(Drawing::for_tree_decomposition(td, &solution.statistics.memo_sizes).write(path, *format)),
+            None => println!("The solver doesn't use a tree decomposition, {} is not saved.", path),
+        }
     }
+}
 
 
 
-    // Act according to the parameters set
-    if benchmark_mode == Some(true) {
-        pretty_unwrap!(start_benchmark(2, 10, 30, 20, 1, 0));
-    } else {
-        let input: SwitchSelectionGraph = pretty_unwrap!(Graph::from_file(&input_path));
-        let problem_instance: SwitchSelectionInstance = pretty_unwrap!(SwitchSelectionInstance::new(input));
-        if let Some(value) = dg_kernel_path {
-            pretty_unwrap!(problem_instance.dg_kernel_for_switch_selection().into_file(&value));
-        }
-        let solver_begin_time: Instant = Instant::now();
-        match solver_name.as_str() {
-            "TreeDecompositionSolver" => {
-                let mut solver: TreeDecompositionSolver = pretty_unwrap!(TreeDecompositionSolver::with_input(problem_instance));
-                pretty_unwrap!(solver.solve());
-                println!("{} solved the problem instance in {} s.", solver_name, solver_begin_time.elapsed().as_secs_f64());
-                let solution: (SwitchSelectionGraph, TapValue) = solver.get_solution().unwrap();
-                println!("Objective value = {}.", solution.1);
-                pretty_unwrap!(solution.0.into_file(&output_path));
-            },
-            "CPLEXSolver" => {
-                let mut solver: CPLEXSolver = pretty_unwrap!(CPLEXSolver::with_input(problem_instance));
-                pretty_unwrap!(solver.solve());
-                println!("{} solved the problem instance in {} s.", solver_name, solver_begin_time.elapsed().as_secs_f64());
-                let solution: (SwitchSelectionGraph, TapValue) = solver.get_solution().unwrap();
-                println!("Objective value = {}.", solution.1);
-                pretty_unwrap!(solution.0.into_file(&output_path));
-            },
-            _ => (),
-        }
+fn main() {
+    let command: Command = match parse_arguments(env::args().skip(1)) {
+        Ok(value) => value,
+        Err(error) => {
+            eprintln!("{}", error);
+            exit(EXIT_USAGE_ERROR);
+        },
+    };
+
+    // Act according to the command
+    match command {
+        Command::Help(help_string) => println!("{}", help_string),
+        Command::Solve(options) => {
+            let problem_instance: SwitchSelectionInstance = read_instance(&options.input_path);
+            if let Some(value) = &options.dg_kernel_path {
+                pretty_unwrap!(formats::write_gnbs(&problem_instance.dg_kernel_for_switch_selection(), value));
+            }
+            // Keep a copy of the instance for the fallback solver
+            let fallback = options.fallback_solver.map(|x| (x, problem_instance.clone()));
+            // Only a solver that refuses the problem instance because of its limits is
+            // replaced with the fallback solver
+            match (run_solver_of_kind(problem_instance, options.solver, &options), fallback) {
+                (Ok(()), _) => (),
+                (Err(error @ SolverError::ResourceLimit { .. }), Some((fallback_solver, fallback_instance))) => {
+                    println!("{} Falling back to {}.", error, fallback_solver);
+                    pretty_unwrap!(run_solver_of_kind(fallback_instance, fallback_solver, &options));
+                },
+                (Err(error), _) => pretty_panic!(error),
+            }
+        },
+        Command::Benchmark(parameters) => {
+            pretty_unwrap!(start_benchmark(&parameters));
+        },
+        Command::Generate(options) => {
+            let graph: SwitchSelectionGraph = generate_distribution_grid(options.treewidth, options.primary_substation_count, &options.generator, options.seed);
+            pretty_unwrap!(formats::write_gnbs(&graph, &options.output_path));
+        },
+        Command::Verify(options) => {
+            let solution: SwitchSelectionInstance = read_instance(&options.input_path);
+            let objective_value: TapValue = pretty_unwrap!(solution.check_configuration());
+            println!("The solution is feasible. Objective value = {}.", objective_value);
+        },
+        Command::Kernel(options) => {
+            let problem_instance: SwitchSelectionInstance = read_instance(&options.input_path);
+            let dg_kernel: SwitchSelectionGraph = problem_instance.dg_kernel_for_switch_selection();
+            match options.output_format {
+                FileFormat::GNBS => pretty_unwrap!(formats::write_gnbs(&dg_kernel, &options.output_path)),
+                FileFormat::PACEGraph => pretty_unwrap!(formats::write_pace_graph(&dg_kernel, &options.output_path)),
+                FileFormat::Drawing(format) => pretty_unwrap!(Drawing::for_dg_kernel(&dg_kernel, problem_instance.unwrap()).write(&options.output_path, format)),
+            }
+        },
+        Command::TreeDecomposition(options) => {
+            let problem_instance: SwitchSelectionInstance = read_instance(&options.input_path);
+            let dg_kernel: SwitchSelectionGraph = problem_instance.dg_kernel_for_switch_selection();
+            let mut td: TreeDecomposition = pretty_unwrap!(TreeDecomposition::with_parameters(&dg_kernel, &options.td_parameters));
+            if options.nice {
+                td = td.to_nice();
+            }
+            if let Some(path) = &options.output_path {
+                pretty_unwrap!(formats::write_pace_tree_decomposition(&td, &dg_kernel, path));
+            }
+            if let Some(path) = &options.graph_output_path {
+                pretty_unwrap!(formats::write_pace_graph(&dg_kernel, path));
+            }
+            if let Some((path, format)) = &options.drawing {
+                pretty_unwrap!(Drawing::for_tree_decomposition(&td, &[]).write(path, *format));
+            }
+            println!("Algorithm = {}, width = {}, # bags = {}, root bag = {}.", td.algorithm, td.max_bag_size - 1, td.count_v(), td.root_id);
+            for bag_id in td.dfs_postordering() {
+                let bag = td.v_attrs(&bag_id).unwrap();
+                println!(
+                    "Bag {}: {}vertices = {:?}, children = {:?}",
+                    bag_id,
+                    match bag.kind {
+                        Some(kind) => format!("{}, ", kind),
+                        None => String::new(),
+                    },
+                    bag.vertices,
+                    td.iter_adjacent_out(&bag_id).unwrap().sorted().collect_vec()
+                );
+            }
+        },
+        Command::Convert(options) => {
+            let input: SwitchSelectionGraph = pretty_unwrap!(formats::read_gnbs(&options.input_path));
+            match options.output_format {
+                FileFormat::GNBS => pretty_unwrap!(formats::write_gnbs(&input, &options.output_path)),
+                FileFormat::PACEGraph => pretty_unwrap!(formats::write_pace_graph(&input, &options.output_path)),
+                FileFormat::Drawing(format) => pretty_unwrap!(Drawing::for_grid(&input).write(&options.output_path, format)),
+            }
+        },
     }
+    exit(EXIT_SUCCESS);
 }
@@ -1,5 +1,6 @@
-use crate::switch_selection_instance::{SwitchSelectionInstance, SwitchSelectionGraph};
-use super::errors::SolverError;
+use std::{sync::{atomic::{AtomicBool, Ordering}, Arc}, time::{Duration, Instant}};
+use crate::{switch_selection_instance::SwitchSelectionInstance, tree_decomposition::TreeDecomposition};
+use super::{errors::SolverError, solution::Solution};
 
 
 
@@ -7,10 +8,133 @@ use super::errors::SolverError;
 
 pub type TapValue = i8;
 
+// Possible values of the square voltage at a primary substation.
+// Tap positions are: T = {-10, ..., 10}.
+// Base voltage: B = {1 + 0.01 * t | t \in T}.
+// Square base voltage: {u² | u \in B}; for each t \in T the  squared  base
+// voltage corresponding to t is BASE_VOLTAGE_SQ[t + 10].
+pub const BASE_VOLTAGE_SQ: [f64; 21] = [0.81, 0.8281, 0.8464, 0.8649, 0.8836, 0.9025, 0.9216, 0.9409, 0.9604, 0.9801, 1.0,
+                                        1.0201, 1.0404, 1.0609, 1.0816, 1.1025, 1.1236, 1.1449, 1.1664, 1.1881, 1.21];
+
+
+
+// Solver-specific data collected during the last call of solve
+#[derive(Clone, Debug, Default)]
+pub struct SolverStatistics {
+    // Width of the tree decomposition used by the solver
+    pub tree_decomposition_width: Option<usize>,
+    // Number of entries in the memo of each bag: (bag ID, # entries)
+    pub memo_sizes: Vec<(usize, usize)>,
+    // Largest number of entries allocated for a table of each bag while its memo
+    // was computed: (bag ID, # entries)
+    pub peak_memo_sizes: Vec<(usize, usize)>,
+    // Total number of memo entries read by the dynamic programming
+    pub processed_memo_entries: usize,
+}
+
+
+
+// Conditions under which solve gives up before it has finished. Only
+// BruteForceSolver returns the best configuration it has found by then, as a
+// solution that is not proven optimal. The tree decomposition solvers have no
+// configuration before the memos of all bags are computed, so they fail with
+// SolverError::Timeout, as does BruteForceSolver if it hasn't found any. CPLEX
+// can't report its incumbent, so CPLEXSolver always fails with it.
+#[derive(Clone, Debug, Default)]
+pub struct SolverLimits {
+    // Wall-clock time limit of each call of solve
+    pub time_limit: Option<Duration>,
+    // solve gives up soon after the token is set, e.g. from another thread
+    pub cancellation_token: Option<Arc<AtomicBool>>,
+}
+
+// SolverLimits::SolverLimits
+impl SolverLimits {
+    // True if solve never gives up
+    pub fn is_unlimited(&self) -> bool {
+        self.time_limit.is_none() && self.cancellation_token.is_none()
+    }
+
+    // Starts the clock of a call of solve. A time limit too large to be added
+    // to the current instant is never reached.
+    pub fn start(&self) -> Deadline {
+        Deadline { instant: self.time_limit.and_then(|x| Instant::now().checked_add(x)), cancellation_token: self.cancellation_token.clone() }
+    }
+}
+
+// SolverLimits of a running call of solve
+#[derive(Clone, Debug)]
+pub struct Deadline {
+    instant: Option<Instant>,
+    cancellation_token: Option<Arc<AtomicBool>>,
+}
+
+// Deadline::Deadline
+impl Deadline {
+    // Fails if the time limit has been exceeded or solve has been  cancelled.
+    // The description of the error begins with solver_name.
+    pub fn check(&self, solver_name: &str) -> Result<(), SolverError> {
+        if self.cancellation_token.as_ref().is_some_and(|x| x.load(Ordering::Relaxed)) {
+            return Err(SolverError::Timeout { description: format!("{}. Solving has been cancelled.", solver_name) });
+        }
+        if self.instant.is_some_and(|x| Instant::now() >= x) {
+            return Err(SolverError::Timeout { description: format!("{}. The time limit has been exceeded.", solver_name) });
+        }
+        Ok(())
+    }
+}
+
 
 
 pub trait BaseSolver: Sized {
     fn with_input(input: SwitchSelectionInstance) -> Result<Self, SolverError>;
-    fn get_solution(&self) -> Option<(SwitchSelectionGraph, TapValue)>;
+    fn get_solution(&self) -> Option<Solution>;
     fn solve(&mut self) -> Result<(), SolverError>;
+    // Limits of the following calls of solve. Solvers that can't enforce the
+    // limits fail with SolverError::InvalidOptions.
+    fn set_limits(&mut self, limits: SolverLimits) -> Result<(), SolverError>;
+
+    fn get_statistics(&self) -> SolverStatistics {
+        SolverStatistics::default()
+    }
+
+    // Solution found by the last successful call of solve. Unlike get_solution,
+    // fails instead of returning None, so that callers don't have to unwrap.
+    fn try_get_solution(&self) -> Result<Solution, SolverError> {
+        self.get_solution().ok_or_else(|| SolverError::BackendFailed {
+            description: "The solver has finished without a solution.".to_string(),
+            source: Box::from("get_solution has returned None after a successful call of solve."),
+        })
+    }
+
+    // Tree decomposition of the DG-kernel the solver works on, if it uses one
+    fn get_tree_decomposition(&self) -> Option<&TreeDecomposition> {
+        None
+    }
+
+    // Estimated peak memory usage of solve in bytes, if the solver can tell
+    fn estimated_memory(&self) -> Option<usize> {
+        None
+    }
+
+    // Refuses to solve the problem instance if it would take more than  budget
+    // bytes of memory. Solvers that can't estimate their memory usage  always
+    // pass the check. The description of the error begins with solver_name.
+    fn check_memory_budget(&self, solver_name: &str, budget: Option<usize>) -> Result<(), SolverError> {
+        match (self.estimated_memory(), budget) {
+            (Some(estimate), Some(budget)) if estimate > budget => Err(memory_budget_error(solver_name, estimate, budget)),
+            _ => Ok(()),
+        }
+    }
+}
+
+// Error of a solver that would take estimate bytes of memory, more than  the
+// budget
+pub fn memory_budget_error(solver_name: &str, estimate: usize, budget: usize) -> SolverError {
+    SolverError::ResourceLimit { description: format!(
+        "{}. Solving the problem instance would take about {} MiB of memory, which exceeds the memory budget of {} MiB.",
+        solver_name,
+        estimate / (1 << 20),
+        budget / (1 << 20)
+    ) }
 }
@@ -1,20 +1,304 @@
-use std::{cmp::Ordering, io::{stdout, Write}, time::Instant};
-use crabnets::{topology_tests::TopologyTests, BasicImmutableGraph, BasicMutableGraph};
+use std::{cmp::Ordering, fmt::Display, fs, str::FromStr, time::Instant};
+use crabnets::{topology_tests::TopologyTests, BasicImmutableGraph, BasicMutableGraph, ImmutableGraphContainer};
 use itertools::Itertools;
 use rand::{Rng, distributions::Uniform, prelude::Distribution, seq::IteratorRandom};
 use rand_xoshiro::{Xoroshiro128PlusPlus, rand_core::SeedableRng};
-use crate::switch_selection_instance::{SwitchSelectionGraph, SwitchSelectionInstance};
-use super::{cplex_solver::CPLEXSolver, base_solver::BaseSolver, errors::SolverError, tree_decomposition_solver::TreeDecompositionSolver};
+use crate::{formats, switch_selection_instance::{SwitchSelectionGraph, SwitchSelectionInstance}};
+use super::{base_solver::BaseSolver, benchmark_results::{CorpusWriter, ResultsFormat, ResultsWriter, SampleRecord, SolverRecord}, errors::{SolverError, UsageError}, registry::SolverKind};
 
 
 
 
 
+macro_rules! io_unwrap {
+    ($expr: expr) => {
+        match $expr {
+            Ok(value) => value,
+            Err(error) => return Err(SolverError::Io { description: "Failed to write benchmark results.".to_string(), source: Box::from(error.to_string()) }),
+        }
+    };
+}
+
+
+
 type PRNG = Xoroshiro128PlusPlus;
 
 
 
-fn random_partial_k_tree(treewidth: usize, vertex_count: u8, prng: &mut PRNG) -> SwitchSelectionGraph {
+#[derive(Clone, Debug)]
+pub struct TimingStatistics {
+    pub times: Vec<f64>,
+    pub mean: f64,
+    pub median: f64,
+    pub min: f64,
+    pub max: f64,
+    pub standard_deviation: f64,
+}
+
+// TimingStatistics::TimingStatistics
+impl TimingStatistics {
+    pub fn from_times(times: Vec<f64>) -> TimingStatistics {
+        if times.is_empty() {
+            return TimingStatistics { times, mean: 0.0, median: 0.0, min: 0.0, max: 0.0, standard_deviation: 0.0 };
+        }
+        let sorted_times = times.iter().cloned().sorted_by(f64::total_cmp).collect_vec();
+        let count = times.len() as f64;
+        let mean = times.iter().sum::<f64>() / count;
+        let median = if sorted_times.len() % 2 == 1 {
+            sorted_times[sorted_times.len() / 2]
+        } else {
+            (sorted_times[sorted_times.len() / 2 - 1] + sorted_times[sorted_times.len() / 2]) / 2.0
+        };
+        // Sample standard deviation; it's 0 if only one run was measured
+        let standard_deviation = if times.len() > 1 {
+            (times.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (count - 1.0)).sqrt()
+        } else {
+            0.0
+        };
+        TimingStatistics { mean, median, min: sorted_times[0], max: *sorted_times.last().unwrap(), standard_deviation, times }
+    }
+}
+
+// TimingStatistics::Display
+impl Display for TimingStatistics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "runs = {}, mean = {} s, median = {} s, min = {} s, max = {} s, std. deviation = {} s",
+            self.times.len(), self.mean, self.median, self.min, self.max, self.standard_deviation
+        )
+    }
+}
+
+
+
+// Topology of the DG-kernel, i.e. of the graph of primary substations
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Topology {
+    // A random partial k-tree as in our PSCC paper
+    PartialKTree,
+    // A planar mesh of city blocks: a lattice with treewidth rows, some  cells
+    // of which are split by a diagonal, with random missing edges
+    UrbanMesh,
+    // A long chain of primary substations with sparse ties  between  primary
+    // substations that are at most treewidth hops away from each other
+    RuralChain,
+}
+
+// Topology::FromStr
+impl FromStr for Topology {
+    type Err = UsageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "k-tree" => Ok(Topology::PartialKTree),
+            "urban" => Ok(Topology::UrbanMesh),
+            "rural" => Ok(Topology::RuralChain),
+            _ => Err(UsageError::from_string(format!("Unknown topology {}.", s))),
+        }
+    }
+}
+
+
+
+// How the resistance and reactance of each edge of a feeder are chosen
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FeederModel {
+    // r and x are sampled uniformly from the given ranges
+    Uniform,
+    // Each feeder is built with a random cable type, and r and x of each edge
+    // are proportional to a random length of the edge
+    CableTypes,
+}
+
+// FeederModel::FromStr
+impl FromStr for FeederModel {
+    type Err = UsageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "uniform" => Ok(FeederModel::Uniform),
+            "cables" => Ok(FeederModel::CableTypes),
+            _ => Err(UsageError::from_string(format!("Unknown feeder model {}.", s))),
+        }
+    }
+}
+
+
+
+// How the active and reactive power of each secondary substation are chosen
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoadModel {
+    // p and q are sampled uniformly from the given ranges
+    Uniform,
+    // Each secondary substation is either residential or  commercial;  the
+    // load is its random peak load scaled by a standard load profile  at  a
+    // random hour of the day, common for the whole grid
+    Profiles,
+}
+
+
+
+// LoadModel::FromStr
+impl FromStr for LoadModel {
+    type Err = UsageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "uniform" => Ok(LoadModel::Uniform),
+            "profiles" => Ok(LoadModel::Profiles),
+            _ => Err(UsageError::from_string(format!("Unknown load model {}.", s))),
+        }
+    }
+}
+
+
+
+// A cable with its resistance and reactance per km in Ohm
+#[derive(Clone, Copy, Debug)]
+pub struct CableType {
+    pub name: &'static str,
+    pub r: f64,
+    pub x: f64,
+}
+
+// Common 20 kV cables and overhead lines
+pub const CABLE_TYPES: [CableType; 4] = [
+    CableType { name: "NA2XS2Y 1x95 RM/25 12/20 kV", r: 0.313, x: 0.123 },
+    CableType { name: "NA2XS2Y 1x150 RM/25 12/20 kV", r: 0.206, x: 0.116 },
+    CableType { name: "NA2XS2Y 1x240 RM/25 12/20 kV", r: 0.125, x: 0.105 },
+    CableType { name: "48-AL1/8-ST1A 20.0", r: 0.5939, x: 0.372 },
+];
+
+// Base impedance in Ohm for the base voltage of 20 kV and the  base  power  of
+// 10 MVA. Used to convert impedances of cables into per unit.
+pub const BASE_IMPEDANCE: f64 = 40.0;
+
+// Standard load profiles normalised to their peak value, one value per hour
+// of the day, shaped after the H0 (residential) and G0 (commercial) profiles.
+pub const RESIDENTIAL_LOAD_PROFILE: [f64; 24] = [0.45, 0.38, 0.34, 0.32, 0.32, 0.35, 0.48, 0.62, 0.66, 0.64, 0.63, 0.66,
+                                                 0.72, 0.68, 0.60, 0.56, 0.58, 0.66, 0.82, 0.95, 1.00, 0.92, 0.78, 0.60];
+pub const COMMERCIAL_LOAD_PROFILE: [f64; 24] = [0.30, 0.28, 0.27, 0.27, 0.28, 0.32, 0.45, 0.70, 0.90, 0.98, 1.00, 0.99,
+                                                0.93, 0.95, 0.97, 0.95, 0.88, 0.75, 0.58, 0.47, 0.40, 0.36, 0.33, 0.31];
+
+
+
+// Parameters of the generator of random distribution grids. All ranges are
+// inclusive for integers and half-open for real numbers. The default  values
+// reproduce the setting of our PSCC paper.
+#[derive(Clone, Debug)]
+pub struct GeneratorParameters {
+    pub topology: Topology,
+    // Probability of a tie between two primary substations of a rural chain
+    pub tie_probability: f64,
+    pub feeder_count: (u8, u8),
+    pub secondary_substation_count: (u8, u8),
+    pub feeder_model: FeederModel,
+    pub r: (f64, f64),
+    pub x: (f64, f64),
+    // Length of an edge of a feeder in km
+    pub edge_length: (f64, f64),
+    pub load_model: LoadModel,
+    pub p: (f64, f64),
+    pub q: (f64, f64),
+    // Probability that a secondary substation is commercial
+    pub commercial_share: f64,
+    // Peak loads of residential and commercial secondary substations  in  per
+    // unit
+    pub residential_peak: (f64, f64),
+    pub commercial_peak: (f64, f64),
+    pub power_factor: (f64, f64),
+}
+
+// GeneratorParameters::Default
+impl Default for GeneratorParameters {
+    fn default() -> Self {
+        GeneratorParameters {
+            topology: Topology::PartialKTree,
+            tie_probability: 0.2,
+            feeder_count: (2, 5),
+            secondary_substation_count: (5, 10),
+            feeder_model: FeederModel::Uniform,
+            r: (0.01, 0.05),
+            x: (0.01, 0.05),
+            edge_length: (0.2, 1.5),
+            load_model: LoadModel::Uniform,
+            p: (-0.5, 0.5),
+            q: (-0.5, 0.5),
+            commercial_share: 0.3,
+            residential_peak: (0.02, 0.08),
+            commercial_peak: (0.05, 0.2),
+            power_factor: (0.9, 0.98),
+        }
+    }
+}
+
+// Parameters of the grids the tests are run on: 3 or 4 primary substations
+// keep them small enough for BruteForceSolver. Partial k-trees need more
+// primary substations than that, so the DG-kernel is an urban mesh for even
+// seeds and a rural chain for odd ones.
+#[cfg(test)]
+pub fn small_grid_parameters(seed: u64) -> GeneratorParameters {
+    GeneratorParameters {
+        topology: if seed % 2 == 0 { Topology::UrbanMesh } else { Topology::RuralChain },
+        tie_probability: 0.5,
+        feeder_count: (1, 2),
+        secondary_substation_count: (1, 3),
+        p: (-0.3, 0.3),
+        q: (-0.3, 0.3),
+        ..GeneratorParameters::default()
+    }
+}
+
+
+
+// Parameters of the benchmark. The default values reproduce the setting of
+// our PSCC paper.
+#[derive(Clone, Debug)]
+pub struct BenchmarkParameters {
+    pub treewidth: (usize, usize),
+    pub primary_substation_count: (usize, usize),
+    pub sample_count: usize,
+    pub sample_repeat: usize,
+    pub sample_ignore: usize,
+    pub generator: GeneratorParameters,
+    pub seed: u64,
+    pub solvers: Vec<SolverKind>,
+    pub check_objectives: bool,
+    pub results: Option<(String, ResultsFormat)>,
+    // Directory to save all generated samples into
+    pub corpus: Option<String>,
+}
+
+// BenchmarkParameters::Default
+impl Default for BenchmarkParameters {
+    fn default() -> Self {
+        BenchmarkParameters {
+            treewidth: (2, 2),
+            primary_substation_count: (10, 30),
+            sample_count: 20,
+            sample_repeat: 1,
+            sample_ignore: 0,
+            generator: GeneratorParameters::default(),
+            seed: 13374,
+            solvers: vec![SolverKind::TreeDecomposition, SolverKind::CPLEX],
+            check_objectives: false,
+            results: None,
+            corpus: None,
+        }
+    }
+}
+
+
+
+#[inline]
+fn sorted_edges(graph: &SwitchSelectionGraph) -> Vec<(usize, usize)> {
+    graph.iter_e().map(|x| (x.id1, x.id2)).sorted().collect()
+}
+
+fn random_partial_k_tree(treewidth: usize, vertex_count: usize, prng: &mut PRNG) -> SwitchSelectionGraph {
+    // A graph with n vertices has treewidth at most n - 1
+    let treewidth = treewidth.min(vertex_count.saturating_sub(1));
     loop {
         let mut answer = SwitchSelectionGraph::new();
         // Populate <answer> with a (treewidth + 1)-clique
@@ -25,7 +309,7 @@ fn random_partial_k_tree(treewidth: usize, vertex_count: u8, prng: &mut PRNG) ->
             }
         }
         // Add other vertices one by one
-        for new_vertex_id in (treewidth + 1)..(vertex_count as usize) {
+        for new_vertex_id in (treewidth + 1)..vertex_count {
             answer.add_v(None);
             // Sample a random clique
             let mut clique_candidate;
@@ -47,7 +331,10 @@ fn random_partial_k_tree(treewidth: usize, vertex_count: u8, prng: &mut PRNG) ->
             }
         }
         // Randomly remove some edges
-        let edges_to_remove_count: usize = prng.sample(Uniform::new(treewidth, answer.count_e() / 2));
+        // Between treewidth and count_e / 2 - 1 edges; fewer if the graph is too
+        // small for that
+        let max_edges_to_remove_count = (answer.count_e() / 2).saturating_sub(1);
+        let edges_to_remove_count: usize = prng.sample(Uniform::new_inclusive(treewidth.min(max_edges_to_remove_count), max_edges_to_remove_count));
         for _ in 0..edges_to_remove_count {
             let edge = answer.iter_e().sorted_by(|x, y|
                 match x.id1.cmp(&y.id1) {
@@ -67,145 +354,394 @@ fn random_partial_k_tree(treewidth: usize, vertex_count: u8, prng: &mut PRNG) ->
     }
 }
 
-pub fn start_benchmark(
-    treewidth: usize,
-    min_primary_substation_count: u8,
-    max_primary_substation_count: u8,
-    sample_count: u8,
-    sample_repeat: u8,
-    sample_ignore: u8
-) -> Result<(), SolverError> {
-    let mut prng = PRNG::seed_from_u64(13374);
+fn random_urban_mesh(treewidth: usize, vertex_count: usize, prng: &mut PRNG) -> SwitchSelectionGraph {
+    // Probability that a city block is split by a diagonal street
+    const DIAGONAL_PROBABILITY: f64 = 0.3;
+    // Probability that a street has no feeders along it
+    const MISSING_EDGE_PROBABILITY: f64 = 0.2;
+    // Primary substations are placed on a lattice column by column, so that
+    // each column has row_count of them
+    let row_count = treewidth.max(1);
+    loop {
+        let mut answer = SwitchSelectionGraph::new();
+        for _ in 0..vertex_count {
+            answer.add_v(None);
+        }
+        for vertex_id in 0..vertex_count {
+            let row = vertex_id % row_count;
+            if row + 1 < row_count && vertex_id + 1 < vertex_count {
+                answer.add_e(&vertex_id, &(vertex_id + 1), false, None).unwrap();
+            }
+            if vertex_id + row_count < vertex_count {
+                answer.add_e(&vertex_id, &(vertex_id + row_count), false, None).unwrap();
+            }
+            if row + 1 < row_count && vertex_id + row_count + 1 < vertex_count && prng.gen_bool(DIAGONAL_PROBABILITY) {
+                answer.add_e(&vertex_id, &(vertex_id + row_count + 1), false, None).unwrap();
+            }
+        }
+        // Randomly remove some edges
+        for (id1, id2) in sorted_edges(&answer) {
+            if prng.gen_bool(MISSING_EDGE_PROBABILITY) && answer.v_degree(&id1).unwrap() > 1 && answer.v_degree(&id2).unwrap() > 1 {
+                answer.remove_e(&id1, &id2, &0).unwrap();
+            }
+        }
+        if answer.is_connected() {
+            return answer;
+        }
+    }
+}
+
+fn random_rural_chain(treewidth: usize, vertex_count: usize, tie_probability: f64, prng: &mut PRNG) -> SwitchSelectionGraph {
+    let mut answer = SwitchSelectionGraph::new();
+    for _ in 0..vertex_count {
+        answer.add_v(None);
+    }
+    for vertex_id in 1..vertex_count {
+        answer.add_e(&(vertex_id - 1), &vertex_id, false, None).unwrap();
+    }
+    // Ties span at most treewidth hops, so that the bandwidth and, therefore,
+    // the treewidth of the chain don't exceed treewidth
+    for vertex_id in 0..vertex_count {
+        for hop_count in 2..=treewidth {
+            if vertex_id + hop_count < vertex_count && prng.gen_bool(tie_probability) {
+                answer.add_e(&vertex_id, &(vertex_id + hop_count), false, None).unwrap();
+            }
+        }
+    }
+    answer
+}
+
+// Replaces each edge of the graph with a random bunch of feeders
+fn replace_edges_with_feeders(graph: &mut SwitchSelectionGraph, parameters: &GeneratorParameters, prng: &mut PRNG) {
     // Random distributions
-    let feeder_count_distribution: Uniform<u8> = Uniform::new(2, 6);
-    let substation_count_distribution: Uniform<u8> = Uniform::new(5, 11);
-    let rx_distribution: Uniform<f64> = Uniform::new(0.01, 0.05);
-    // Benchmarking results
-    let mut cplex_max_times: Vec<f64> = Vec::with_capacity((max_primary_substation_count - min_primary_substation_count + 1) as usize);
-    let mut cplex_avg_times: Vec<f64> = Vec::with_capacity((max_primary_substation_count - min_primary_substation_count + 1) as usize);
-    let mut cplex_min_times: Vec<f64> = Vec::with_capacity((max_primary_substation_count - min_primary_substation_count + 1) as usize);
-    let mut td_max_times: Vec<f64> = Vec::with_capacity((max_primary_substation_count - min_primary_substation_count + 1) as usize);
-    let mut td_avg_times: Vec<f64> = Vec::with_capacity((max_primary_substation_count - min_primary_substation_count + 1) as usize);
-    let mut td_min_times: Vec<f64> = Vec::with_capacity((max_primary_substation_count - min_primary_substation_count + 1) as usize);
-    // Test random distribution grids
-    for primary_substation_count in min_primary_substation_count..=max_primary_substation_count {
-        println!("Treewidth = {}, # primary substations = {}", treewidth, primary_substation_count);
-        let mut cplex_times: Vec<f64> = Vec::with_capacity(sample_count as usize);
-        let mut td_times: Vec<f64> = Vec::with_capacity(sample_count as usize);
-        let mut successful_samples: u8 = 0;
-        let mut restart = false;
-        loop {
-            if restart {
-                print!("X");
-                stdout().flush().unwrap();
-            } else {
-                print!("\tSample {}\n\t\t", successful_samples + 1);
+    let feeder_count_distribution: Uniform<u8> = Uniform::new_inclusive(parameters.feeder_count.0, parameters.feeder_count.1);
+    let substation_count_distribution: Uniform<u8> = Uniform::new_inclusive(parameters.secondary_substation_count.0, parameters.secondary_substation_count.1);
+    let r_distribution: Uniform<f64> = Uniform::new(parameters.r.0, parameters.r.1);
+    let x_distribution: Uniform<f64> = Uniform::new(parameters.x.0, parameters.x.1);
+    let edge_length_distribution: Uniform<f64> = Uniform::new(parameters.edge_length.0, parameters.edge_length.1);
+    let p_distribution: Uniform<f64> = Uniform::new(parameters.p.0, parameters.p.1);
+    let q_distribution: Uniform<f64> = Uniform::new(parameters.q.0, parameters.q.1);
+    let residential_peak_distribution: Uniform<f64> = Uniform::new(parameters.residential_peak.0, parameters.residential_peak.1);
+    let commercial_peak_distribution: Uniform<f64> = Uniform::new(parameters.commercial_peak.0, parameters.commercial_peak.1);
+    let power_factor_distribution: Uniform<f64> = Uniform::new(parameters.power_factor.0, parameters.power_factor.1);
+    // The hour of the day is common for the whole grid
+    let hour: usize = match parameters.load_model {
+        LoadModel::Uniform => 0,
+        LoadModel::Profiles => prng.gen_range(0..24),
+    };
+    let sample_load = |prng: &mut PRNG| -> (f64, f64) {
+        match parameters.load_model {
+            LoadModel::Uniform => (p_distribution.sample(prng), q_distribution.sample(prng)),
+            LoadModel::Profiles => {
+                let p = if prng.gen_bool(parameters.commercial_share) {
+                    commercial_peak_distribution.sample(prng) * COMMERCIAL_LOAD_PROFILE[hour]
+                } else {
+                    residential_peak_distribution.sample(prng) * RESIDENTIAL_LOAD_PROFILE[hour]
+                };
+                let power_factor: f64 = power_factor_distribution.sample(prng);
+                (p, p * (1.0 / power_factor.powi(2) - 1.0).sqrt())
+            },
+        }
+    };
+    let sample_impedance = |cable: &Option<CableType>, prng: &mut PRNG| -> (f64, f64) {
+        match cable {
+            None => (r_distribution.sample(prng), x_distribution.sample(prng)),
+            Some(cable) => {
+                let edge_length: f64 = edge_length_distribution.sample(prng);
+                (cable.r * edge_length / BASE_IMPEDANCE, cable.x * edge_length / BASE_IMPEDANCE)
+            },
+        }
+    };
+    for (id1, id2) in sorted_edges(graph) {
+        let feeder_count: u8 = feeder_count_distribution.sample(prng);
+        graph.v_attrs_mut(&id1).unwrap().tap_position = Some(0);
+        graph.v_attrs_mut(&id2).unwrap().tap_position = Some(0);
+        graph.remove_e(&id1, &id2, &0).unwrap();
+        for _ in 0..feeder_count {
+            let substation_count: u8 = substation_count_distribution.sample(prng);
+            let cable: Option<CableType> = match parameters.feeder_model {
+                FeederModel::Uniform => None,
+                FeederModel::CableTypes => Some(CABLE_TYPES[prng.gen_range(0..CABLE_TYPES.len())]),
+            };
+            let mut last_substation_id = id1;
+            for _ in 0..substation_count {
+                let new_substation_id: usize = graph.add_v(None);
+                {
+                    let (p, q) = sample_load(prng);
+                    let attributes = graph.v_attrs_mut(&new_substation_id).unwrap();
+                    attributes.p = p;
+                    attributes.q = q;
+                }
+                graph.add_e(&last_substation_id, &new_substation_id, false, None).unwrap();
+                {
+                    let (r, x) = sample_impedance(&cable, prng);
+                    let attributes = graph.e_attrs_mut(&last_substation_id, &new_substation_id, &0).unwrap();
+                    attributes.r = r;
+                    attributes.x = x;
+                }
+                last_substation_id = new_substation_id;
             }
-            // Generate a random partial k-tree
-            let mut graph: SwitchSelectionGraph = random_partial_k_tree(treewidth, primary_substation_count, &mut prng);
-            print!("P");
-            stdout().flush().unwrap();
-            // Replace each edge of the graph with a bunch of feeders
-            let old_edges = graph.iter_e().sorted_by(|x, y|
-                match x.id1.cmp(&y.id1) {
-                    Ordering::Less => Ordering::Less,
-                    Ordering::Equal => x.id2.cmp(&y.id2),
-                    Ordering::Greater => Ordering::Greater,
+            graph.add_e(&last_substation_id, &id2, false, None).unwrap();
+            {
+                let (r, x) = sample_impedance(&cable, prng);
+                let attributes = graph.e_attrs_mut(&last_substation_id, &id2, &0).unwrap();
+                attributes.r = r;
+                attributes.x = x;
+            }
+        }
+    }
+}
+
+// Generates a random distribution grid whose DG-kernel has the given number
+// of primary substations and treewidth at most treewidth
+fn random_distribution_grid(treewidth: usize, primary_substation_count: usize, parameters: &GeneratorParameters, prng: &mut PRNG) -> SwitchSelectionGraph {
+    let mut graph: SwitchSelectionGraph = match parameters.topology {
+        Topology::PartialKTree => random_partial_k_tree(treewidth, primary_substation_count, prng),
+        Topology::UrbanMesh => random_urban_mesh(treewidth, primary_substation_count, prng),
+        Topology::RuralChain => random_rural_chain(treewidth, primary_substation_count, parameters.tie_probability, prng),
+    };
+    replace_edges_with_feeders(&mut graph, parameters, prng);
+    graph
+}
+
+// Generates a single random distribution grid, e.g. to save it as GNBS
+pub fn generate_distribution_grid(treewidth: usize, primary_substation_count: usize, parameters: &GeneratorParameters, seed: u64) -> SwitchSelectionGraph {
+    let mut prng = PRNG::seed_from_u64(seed);
+    random_distribution_grid(treewidth, primary_substation_count, parameters, &mut prng)
+}
+
+pub fn start_benchmark(parameters: &BenchmarkParameters) -> Result<(), SolverError> {
+    let mut prng = PRNG::seed_from_u64(parameters.seed);
+    println!("Seed = {}", parameters.seed);
+    // Skip the solvers that can't be used on this machine
+    let mut solvers: Vec<SolverKind> = Vec::with_capacity(parameters.solvers.len());
+    for &solver in parameters.solvers.iter() {
+        if solver.is_available() {
+            solvers.push(solver);
+        } else {
+            println!("{} is not available on this machine and will be skipped.", solver);
+        }
+    }
+    if solvers.is_empty() {
+        return Err(SolverError::BackendUnavailable { description: "None of the requested solvers is available on this machine.".to_string(), source: None });
+    }
+    let mut results_writer: Option<ResultsWriter> = match &parameters.results {
+        Some((path, format)) => Some(io_unwrap!(ResultsWriter::create(path, *format, &solvers))),
+        None => None,
+    };
+    let mut corpus_writer: Option<CorpusWriter> = match &parameters.corpus {
+        Some(path) => Some(io_unwrap!(CorpusWriter::create(path))),
+        None => None,
+    };
+    for treewidth in parameters.treewidth.0..=parameters.treewidth.1 {
+        // Graphs with at most treewidth vertices can't have the requested treewidth
+        let min_primary_substation_count = parameters.primary_substation_count.0.max(treewidth + 1);
+        // Test random distribution grids
+        for primary_substation_count in min_primary_substation_count..=parameters.primary_substation_count.1 {
+            println!("Treewidth = {}, # primary substations = {}", treewidth, primary_substation_count);
+            let mut times: Vec<Vec<f64>> = vec![Vec::with_capacity(parameters.sample_count); solvers.len()];
+            let mut successful_samples: usize = 0;
+            let mut sample_i: usize = 0;
+            while successful_samples < parameters.sample_count {
+                sample_i += 1;
+                // Generate a random DG-kernel and replace each of its edges with a  bunch  of
+                // feeders
+                let graph: SwitchSelectionGraph = random_distribution_grid(treewidth, primary_substation_count, &parameters.generator, &mut prng);
+                if let Some(writer) = corpus_writer.as_ref() {
+                    let path = writer.instance_path(parameters.seed, treewidth, primary_substation_count, sample_i);
+                    formats::write_gnbs(&graph, &path).map_err(|error| SolverError::Io { description: error.to_string(), source: Box::new(error) })?;
                 }
-            );
-            for old_edge in old_edges {
-                let feeder_count: u8 = feeder_count_distribution.sample(&mut prng);
-                graph.v_attrs_mut(&old_edge.id1).unwrap().tap_position = Some(0);
-                graph.v_attrs_mut(&old_edge.id2).unwrap().tap_position = Some(0);
-                graph.remove_e(&old_edge.id1, &old_edge.id2, &0).unwrap();
-                for _ in 0..feeder_count {
-                    let substation_count: u8 = substation_count_distribution.sample(&mut prng);
-                    let mut last_substation_id = old_edge.id1;
-                    let pq_distribution: Uniform<f64> = Uniform::new(-0.5, 0.5);
-                    for _ in 0..substation_count {
-                        let new_substation_id: usize = graph.add_v(None);
-                        {
-                            let attributes = graph.v_attrs_mut(&new_substation_id).unwrap();
-                            attributes.p = pq_distribution.sample(&mut prng);
-                            attributes.q = pq_distribution.sample(&mut prng);
-                        }
-                        graph.add_e(&last_substation_id, &new_substation_id, false, None).unwrap();
-                        {
-                            let attributes = graph.e_attrs_mut(&last_substation_id, &new_substation_id, &0).unwrap();
-                            attributes.r = rx_distribution.sample(&mut prng);
-                            attributes.x = rx_distribution.sample(&mut prng);
-                        }
-                        last_substation_id = new_substation_id;
+                // Create a problem instance instance out of graph
+                let instance = SwitchSelectionInstance::new(graph).map_err(|error| SolverError::InvalidOptions { description: format!(
+                    "Sample {} (treewidth = {}, # primary substations = {}, seed = {}) is not a valid distribution grid. {}",
+                    sample_i, treewidth, primary_substation_count, parameters.seed, error
+                ) })?;
+                let mut record = SampleRecord {
+                    seed: parameters.seed,
+                    treewidth,
+                    primary_substation_count,
+                    secondary_substation_count: instance.count_v() - primary_substation_count,
+                    sample_i,
+                    tree_decomposition_width: None,
+                    memo_entries_total: None,
+                    memo_entries_max: None,
+                    memo_entries_peak: None,
+                    memo_entries_processed: None,
+                    feasible: true,
+                    solvers: Vec::with_capacity(solvers.len()),
+                };
+                // Time all solvers one after another. Every solver runs even if another one
+                // found the sample infeasible, so that their verdicts can be compared.  A
+                // solver that refuses the sample because of its limits is  skipped.  Other
+                // errors stop the benchmark.
+                let mut resource_limit_error: Option<SolverError> = None;
+                for &solver in solvers.iter() {
+                    match solver.timeit(instance.clone(), parameters.sample_repeat, parameters.sample_ignore) {
+                        Ok(run) => {
+                            if run.statistics.tree_decomposition_width.is_some() {
+                                record.tree_decomposition_width = run.statistics.tree_decomposition_width;
+                                record.memo_entries_total = Some(run.statistics.memo_sizes.iter().map(|x| x.1).sum());
+                                record.memo_entries_max = run.statistics.memo_sizes.iter().map(|x| x.1).max();
+                                record.memo_entries_peak = run.statistics.peak_memo_sizes.iter().map(|x| x.1).max();
+                                record.memo_entries_processed = Some(run.statistics.processed_memo_entries);
+                            }
+                            record.solvers.push(SolverRecord {
+                                solver,
+                                time: Some(run.timing.mean),
+                                objective_value: Some(run.objective_value),
+                                feasible: Some(true),
+                                peak_memory: run.peak_memory,
+                            });
+                        },
+                        Err(SolverError::Infeasible { .. }) => {
+                            record.solvers.push(SolverRecord { solver, time: None, objective_value: None, feasible: Some(false), peak_memory: None });
+                        },
+                        Err(error @ SolverError::ResourceLimit { .. }) => {
+                            println!("\t{} skipped sample {}. {}", solver, sample_i, error);
+                            record.solvers.push(SolverRecord { solver, time: None, objective_value: None, feasible: None, peak_memory: None });
+                            resource_limit_error = Some(error);
+                        },
+                        Err(error) => return Err(error),
                     }
-                    graph.add_e(&last_substation_id, &old_edge.id2, false, None).unwrap();
-                    {
-                        let attributes = graph.e_attrs_mut(&last_substation_id, &old_edge.id2, &0).unwrap();
-                        attributes.r = rx_distribution.sample(&mut prng);
-                        attributes.x = rx_distribution.sample(&mut prng);
+                }
+                // Nothing can be measured if every solver has skipped the sample
+                if let Some(error) = resource_limit_error {
+                    if record.solvers.iter().all(|x| x.feasible.is_none()) {
+                        return Err(error);
                     }
                 }
-            }
-            print!("D");
-            stdout().flush().unwrap();
-            // Create a problem instance instance out of graph
-            let instance = SwitchSelectionInstance::new(graph).unwrap();
-            // Time the TreeDecompositionSolver
-            let mut solver: TreeDecompositionSolver = TreeDecompositionSolver::with_input(instance.clone())?;
-            match timeit(&mut solver, sample_repeat, sample_ignore) {
-                Ok(value) => td_times.push(value),
-                Err(_) => {
-                    restart = true;
-                    continue;
-                },
-            }
-            println!("\n\t\tTreeDecompositionSolver finished ({} s). Optimal value = {}.", td_times.last().unwrap(), solver.get_solution().unwrap().1);
-            // Time the CPLEXSolver
-            let mut solver: CPLEXSolver = CPLEXSolver::with_input(instance.clone())?;
-            match timeit(&mut solver, sample_repeat, sample_ignore) {
-                Ok(value) => cplex_times.push(value),
-                Err(_) => {
-                    restart = true;
+                let launched_solvers = record.solvers.iter().filter(|x| x.feasible.is_some()).collect_vec();
+                record.feasible = launched_solvers.iter().all(|x| x.feasible == Some(true));
+                if parameters.check_objectives && launched_solvers.iter().map(|x| x.objective_value).unique().count() > 1 {
+                    return Err(SolverError::InconsistentResults { description: format!(
+                        "Solvers disagree on sample {} (treewidth = {}, # primary substations = {}, seed = {}): {}.",
+                        sample_i, treewidth, primary_substation_count, parameters.seed,
+                        launched_solvers.iter().map(|x| format!("{} = {}", x.solver, match x.objective_value {
+                            Some(value) => value.to_string(),
+                            None => "infeasible".to_string(),
+                        })).join(", ")
+                    ) });
+                }
+                if let Some(writer) = results_writer.as_mut() {
+                    io_unwrap!(writer.write(&record));
+                }
+                if let Some(writer) = corpus_writer.as_mut() {
+                    io_unwrap!(writer.write(&record));
+                }
+                if !record.feasible {
+                    println!(
+                        "\tSample {}: infeasible according to {}.",
+                        sample_i,
+                        record.solvers.iter().filter(|x| x.feasible == Some(false)).map(|x| x.solver).join(", ")
+                    );
                     continue;
-                },
+                }
+                println!(
+                    "\tSample {}: {}.",
+                    sample_i,
+                    launched_solvers.iter().map(|x| format!(
+                        "{} finished ({} s{}), optimal value = {}",
+                        x.solver,
+                        x.time.unwrap(),
+                        match x.peak_memory {
+                            Some(value) => format!(", peak memory = {} KiB", value),
+                            None => String::new(),
+                        },
+                        x.objective_value.unwrap()
+                    )).join("; ")
+                );
+                for (solver_i, solver_record) in record.solvers.iter().enumerate() {
+                    if let Some(time) = solver_record.time {
+                        times[solver_i].push(time);
+                    }
+                }
+                successful_samples += 1;
             }
-            println!("\t\tCPLEXSolver finished ({} s). Optimal value = {}.", cplex_times.last().unwrap(), solver.get_solution().unwrap().1);
-            successful_samples += 1;
-            restart = false;
-            if successful_samples == sample_count {
-                break;
+            // Print the summary for this number of primary substations
+            for (solver_i, solver) in solvers.iter().enumerate() {
+                println!("\t{}: {}.", solver, TimingStatistics::from_times(times[solver_i].clone()));
             }
         }
-        // Print the results for this number of primary substations
-        println!("\tResults for this # primary substations");
-        println!("\tTreeDecompositionSolver: {:?}", td_times);
-        println!("\tCPLEXSolver: {:?}", cplex_times);
-        // Remember the results for this number of primary substations
-        cplex_max_times.push(cplex_times.iter().map(|x: &f64| *x).reduce(f64::max).unwrap());
-        cplex_avg_times.push(cplex_times.iter().sum::<f64>() / sample_count as f64);
-        cplex_min_times.push(cplex_times.iter().map(|x: &f64| *x).reduce(f64::min).unwrap());
-        td_max_times.push(td_times.iter().map(|x: &f64| *x).reduce(f64::max).unwrap());
-        td_avg_times.push(td_times.iter().sum::<f64>() / sample_count as f64);
-        td_min_times.push(td_times.iter().map(|x: &f64| *x).reduce(f64::min).unwrap());
-    }
-    println!("Results");
-    println!("TreeDecompositionSolver max: {:?}", td_max_times);
-    println!("TreeDecompositionSolver avg: {:?}", td_avg_times);
-    println!("TreeDecompositionSolver min: {:?}", td_min_times);
-    println!("CPLEXSolver max: {:?}", cplex_max_times);
-    println!("CPLEXSolver avg: {:?}", cplex_avg_times);
-    println!("CPLEXSolver min: {:?}", cplex_min_times);
+    }
+    if let Some(writer) = results_writer {
+        io_unwrap!(writer.finish());
+    }
     Ok(())
 }
 
-pub fn timeit<S: BaseSolver>(solver: &mut S, repeat: u8, ignore: u8) -> Result<f64, SolverError> {
+// Peak resident set size of this process in KiB. Only available on Linux.
+pub fn peak_resident_memory() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    status.lines().find(|x| x.starts_with("VmHWM:"))?.split_whitespace().nth(1)?.parse().ok()
+}
+
+// Resets the peak resident set size so that  peak_resident_memory  reports  the
+// peak reached after this call. Does nothing if the OS doesn't support it.
+pub fn reset_peak_resident_memory() {
+    fs::write("/proc/self/clear_refs", "5").unwrap_or(());
+}
+
+pub fn timeit<S: BaseSolver>(solver: &mut S, repeat: usize, ignore: usize) -> Result<TimingStatistics, SolverError> {
     for _ in 0..ignore {
         solver.solve()?;
     }
-    let mut cumulative_time: f64 = 0.0;
+    let mut times: Vec<f64> = Vec::with_capacity(repeat - ignore);
     for _ in 0..(repeat - ignore) {
         let solver_begin_time: Instant = Instant::now();
         solver.solve()?;
-        cumulative_time += solver_begin_time.elapsed().as_secs_f64();
+        times.push(solver_begin_time.elapsed().as_secs_f64());
+    }
+    Ok(TimingStatistics::from_times(times))
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+    use crabnets::BasicImmutableGraph;
+    use super::{generate_distribution_grid, small_grid_parameters, start_benchmark, BenchmarkParameters, GeneratorParameters, Topology};
+    use crate::solver::registry::SolverKind;
+
+    #[test]
+    fn tiny_partial_k_trees_are_generated() {
+        let parameters = GeneratorParameters { topology: Topology::PartialKTree, ..GeneratorParameters::default() };
+        for (treewidth, primary_substation_count) in [(1, 2), (2, 2), (2, 3), (3, 3), (2, 4), (5, 4)] {
+            for seed in 0..5 {
+                let grid = generate_distribution_grid(treewidth, primary_substation_count, &parameters, seed);
+                let primary_substations = grid.iter_v().filter(|x| grid.v_attrs(x).unwrap().tap_position.is_some()).count();
+                assert_eq!(primary_substations, primary_substation_count, "treewidth = {}, primaries = {}, seed = {}", treewidth, primary_substation_count, seed);
+            }
+        }
+    }
+    #[test]
+    fn corpora_of_several_seeds_share_one_manifest() {
+        let directory = env::temp_dir().join(format!("switch_selection_corpus_{}", std::process::id()));
+        fs::remove_dir_all(&directory).unwrap_or(());
+        for seed in [1, 2] {
+            let parameters = BenchmarkParameters {
+                treewidth: (1, 1),
+                primary_substation_count: (2, 2),
+                sample_count: 1,
+                generator: small_grid_parameters(seed),
+                seed,
+                solvers: vec![SolverKind::TreeDecomposition],
+                corpus: Some(directory.to_string_lossy().into_owned()),
+                ..BenchmarkParameters::default()
+            };
+            start_benchmark(&parameters).unwrap();
+        }
+        let manifest = fs::read_to_string(directory.join("manifest.csv")).unwrap();
+        let lines: Vec<&str> = manifest.lines().collect();
+        assert_eq!(lines.iter().filter(|x| x.starts_with("file,")).count(), 1);
+        assert!(lines[0].starts_with("file,"));
+        for seed in [1, 2] {
+            let rows: Vec<&str> = lines.iter().filter(|x| x.starts_with(&format!("s{}_", seed))).cloned().collect();
+            assert!(!rows.is_empty(), "seed = {}", seed);
+            for row in rows {
+                assert!(directory.join(row.split(',').next().unwrap()).is_file(), "{}", row);
+            }
+        }
+        fs::remove_dir_all(&directory).unwrap();
     }
-    Ok(cumulative_time / repeat as f64)
 }
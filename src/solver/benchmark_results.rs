@@ -0,0 +1,226 @@
+use std::{fs::{self, File, OpenOptions}, io::{self, BufWriter, Write}, path::{Path, PathBuf}};
+use itertools::Itertools;
+use super::{base_solver::TapValue, errors::UsageError, registry::SolverKind};
+
+
+
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResultsFormat {
+    CSV,
+    JSON,
+}
+
+// ResultsFormat::ResultsFormat
+impl ResultsFormat {
+    pub fn from_path(path: &str) -> Result<Self, UsageError> {
+        match Path::new(path).extension().and_then(|x| x.to_str()) {
+            Some("csv") => Ok(ResultsFormat::CSV),
+            Some("json") => Ok(ResultsFormat::JSON),
+            _ => Err(UsageError::from_string(format!("Can't deduce the format of benchmark results from the extension of {}: use .csv or .json.", path))),
+        }
+    }
+}
+
+
+
+// Result of a single solver on a single sample
+#[derive(Clone, Debug)]
+pub struct SolverRecord {
+    pub solver: SolverKind,
+    // Mean time of the measured runs in seconds
+    pub time: Option<f64>,
+    pub objective_value: Option<TapValue>,
+    // None if the solver skipped the sample because it exceeds the  limits  of
+    // the solver (SolverError::ResourceLimit); then time, objective_value  and
+    // peak_memory are None as well
+    pub feasible: Option<bool>,
+    // Peak resident set size in KiB
+    pub peak_memory: Option<u64>,
+}
+
+// Everything we know about a single generated sample
+#[derive(Clone, Debug)]
+pub struct SampleRecord {
+    pub seed: u64,
+    pub treewidth: usize,
+    pub primary_substation_count: usize,
+    pub secondary_substation_count: usize,
+    // Index of the sample among all samples generated for this treewidth  and
+    // number of primary substations, including infeasible ones
+    pub sample_i: usize,
+    pub tree_decomposition_width: Option<usize>,
+    pub memo_entries_total: Option<usize>,
+    pub memo_entries_max: Option<usize>,
+    pub memo_entries_peak: Option<usize>,
+    pub memo_entries_processed: Option<usize>,
+    pub feasible: bool,
+    pub solvers: Vec<SolverRecord>,
+}
+
+
+
+#[inline]
+fn optional_to_string<T: ToString>(value: &Option<T>, none: &str) -> String {
+    match value {
+        Some(value) => value.to_string(),
+        None => none.to_string(),
+    }
+}
+
+
+
+pub struct ResultsWriter {
+    format: ResultsFormat,
+    writer: BufWriter<File>,
+    record_count: usize,
+}
+
+// ResultsWriter::ResultsWriter
+impl ResultsWriter {
+    pub fn create(path: &str, format: ResultsFormat, solvers: &[SolverKind]) -> io::Result<ResultsWriter> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        match format {
+            ResultsFormat::CSV => {
+                let solver_columns = solvers.iter().map(|x| format!("{0}_time,{0}_objective,{0}_feasible,{0}_peak_memory_kib", x)).join(",");
+                writeln!(
+                    writer,
+                    "seed,treewidth,primary_substations,secondary_substations,sample,td_width,memo_entries_total,memo_entries_max,memo_entries_peak,memo_entries_processed,feasible,{}",
+                    solver_columns
+                )?;
+            },
+            ResultsFormat::JSON => write!(writer, "[")?,
+        }
+        writer.flush()?;
+        Ok(ResultsWriter { format, writer, record_count: 0 })
+    }
+
+    // Records are written as soon as they are available, so  that  results  of
+    // an interrupted benchmark aren't lost.
+    pub fn write(&mut self, record: &SampleRecord) -> io::Result<()> {
+        match self.format {
+            ResultsFormat::CSV => {
+                let solver_columns = record.solvers.iter().map(|x| format!(
+                    "{},{},{},{}",
+                    optional_to_string(&x.time, ""),
+                    optional_to_string(&x.objective_value, ""),
+                    optional_to_string(&x.feasible, ""),
+                    optional_to_string(&x.peak_memory, "")
+                )).join(",");
+                writeln!(
+                    self.writer,
+                    "{},{},{},{},{},{},{},{},{},{},{},{}",
+                    record.seed,
+                    record.treewidth,
+                    record.primary_substation_count,
+                    record.secondary_substation_count,
+                    record.sample_i,
+                    optional_to_string(&record.tree_decomposition_width, ""),
+                    optional_to_string(&record.memo_entries_total, ""),
+                    optional_to_string(&record.memo_entries_max, ""),
+                    optional_to_string(&record.memo_entries_peak, ""),
+                    optional_to_string(&record.memo_entries_processed, ""),
+                    record.feasible,
+                    solver_columns
+                )?;
+            },
+            ResultsFormat::JSON => {
+                let solver_objects = record.solvers.iter().map(|x| format!(
+                    "{{\"solver\": \"{}\", \"time\": {}, \"objective\": {}, \"feasible\": {}, \"peak_memory_kib\": {}}}",
+                    x.solver,
+                    optional_to_string(&x.time, "null"),
+                    optional_to_string(&x.objective_value, "null"),
+                    optional_to_string(&x.feasible, "null"),
+                    optional_to_string(&x.peak_memory, "null")
+                )).join(", ");
+                write!(
+                    self.writer,
+                    "{}\n  {{\"seed\": {}, \"treewidth\": {}, \"primary_substations\": {}, \"secondary_substations\": {}, \"sample\": {}, \
+                    \"td_width\": {}, \"memo_entries_total\": {}, \"memo_entries_max\": {}, \"memo_entries_peak\": {}, \"memo_entries_processed\": {}, \
+                    \"feasible\": {}, \"solvers\": [{}]}}",
+                    if self.record_count > 0 { "," } else { "" },
+                    record.seed,
+                    record.treewidth,
+                    record.primary_substation_count,
+                    record.secondary_substation_count,
+                    record.sample_i,
+                    optional_to_string(&record.tree_decomposition_width, "null"),
+                    optional_to_string(&record.memo_entries_total, "null"),
+                    optional_to_string(&record.memo_entries_max, "null"),
+                    optional_to_string(&record.memo_entries_peak, "null"),
+                    optional_to_string(&record.memo_entries_processed, "null"),
+                    record.feasible,
+                    solver_objects
+                )?;
+            },
+        }
+        self.record_count += 1;
+        self.writer.flush()
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        if self.format == ResultsFormat::JSON {
+            writeln!(self.writer, "\n]")?;
+        }
+        self.writer.flush()
+    }
+}
+
+
+
+// Saves every generated sample into a directory as a GNBS file and lists  the
+// samples in manifest.csv, so that a problematic sample can be solved  again
+// without regenerating the whole sequence of samples before it. The manifest
+// of an existing corpus is appended to.
+pub struct CorpusWriter {
+    directory: PathBuf,
+    manifest: BufWriter<File>,
+}
+
+// CorpusWriter::CorpusWriter
+impl CorpusWriter {
+    pub fn create(directory: &str) -> io::Result<CorpusWriter> {
+        fs::create_dir_all(directory)?;
+        let directory = PathBuf::from(directory);
+        let file = OpenOptions::new().create(true).append(true).open(directory.join("manifest.csv"))?;
+        let is_empty = file.metadata()?.len() == 0;
+        let mut manifest = BufWriter::new(file);
+        if is_empty {
+            writeln!(manifest, "file,seed,treewidth,primary_substations,secondary_substations,sample,label,objective")?;
+            manifest.flush()?;
+        }
+        Ok(CorpusWriter { directory, manifest })
+    }
+
+    // The seed is part of the name so that corpora of  benchmarks  run  with
+    // different seeds can be saved into the same directory
+    pub fn file_name(seed: u64, treewidth: usize, primary_substation_count: usize, sample_i: usize) -> String {
+        format!("s{}_tw{}_n{}_sample{}.gnbs", seed, treewidth, primary_substation_count, sample_i)
+    }
+
+    pub fn instance_path(&self, seed: u64, treewidth: usize, primary_substation_count: usize, sample_i: usize) -> String {
+        self.directory.join(Self::file_name(seed, treewidth, primary_substation_count, sample_i)).to_string_lossy().into_owned()
+    }
+
+    // The instance itself must be saved with instance_path beforehand
+    pub fn write(&mut self, record: &SampleRecord) -> io::Result<()> {
+        // Solvers agree on the optimal value unless the benchmark is run  without
+        // --check-objectives, so the first optimal value reported by any  solver
+        // is saved
+        let objective_value = if record.feasible { record.solvers.iter().find_map(|x| x.objective_value) } else { None };
+        writeln!(
+            self.manifest,
+            "{},{},{},{},{},{},{},{}",
+            Self::file_name(record.seed, record.treewidth, record.primary_substation_count, record.sample_i),
+            record.seed,
+            record.treewidth,
+            record.primary_substation_count,
+            record.secondary_substation_count,
+            record.sample_i,
+            if record.feasible { "feasible" } else { "infeasible" },
+            optional_to_string(&objective_value, "")
+        )?;
+        self.manifest.flush()
+    }
+}
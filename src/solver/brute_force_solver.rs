@@ -0,0 +1,152 @@
+use crabnets::{BasicImmutableGraph, ImmutableGraphContainer};
+use itertools::Itertools;
+use crate::switch_selection_instance::SwitchSelectionInstance;
+use super::{base_solver::*, errors::SolverError, solution::{LineCut, Solution}};
+
+
+
+
+
+// Largest number of primary substations the solver accepts: it tries all 21^n
+// combinations of tap positions
+pub const MAX_PRIMARY_SUBSTATION_COUNT: usize = 5;
+
+
+
+// Reference solver for tiny instances. It tries every combination of  tap
+// positions and every cut of each line, computing the voltages with
+// SwitchSelectionInstance::line_voltages_sq, and shares no code with the other
+// solvers, so it can serve as an oracle for them.
+pub struct BruteForceSolver {
+    input: SwitchSelectionInstance,
+    primary_substations: Vec<usize>,
+    lines: Vec<Vec<usize>>,
+    // Tap positions of the primary substations and the index of the opened edge
+    // of each line in the optimal solution
+    solution: Option<(Vec<TapValue>, Vec<usize>)>,
+    // Whether all combinations of tap positions were tried
+    optimal: bool,
+    limits: SolverLimits,
+}
+
+// BruteForceSolver::BruteForceSolver
+impl BruteForceSolver {
+    // Finds the first cut of the line for which all squared voltages lie in
+    // [0.81, 1.21] given the tap positions at its endpoints
+    fn feasible_cut(&self, line: &[usize], left_tap_position: TapValue, right_tap_position: TapValue) -> Option<usize> {
+        (0..(line.len() - 1)).find(|&cut_i|
+            self.input
+                .line_voltages_sq(line, cut_i, left_tap_position, right_tap_position)
+                .into_iter()
+                .all(|x| (0.81..=1.21).contains(&x))
+        )
+    }
+}
+
+// BruteForceSolver::BaseSolver
+impl BaseSolver for BruteForceSolver {
+    fn with_input(input: SwitchSelectionInstance) -> Result<Self, SolverError> {
+        let primary_substations = input.iter_v().filter(|x| input.v_attrs(x).unwrap().tap_position.is_some()).sorted().collect_vec();
+        if primary_substations.len() > MAX_PRIMARY_SUBSTATION_COUNT {
+            return Err(SolverError::ResourceLimit { description: format!(
+                "BruteForceSolver. The problem instance has {} primary substations, but at most {} are supported.",
+                primary_substations.len(),
+                MAX_PRIMARY_SUBSTATION_COUNT
+            ) });
+        }
+        let lines = input.lines();
+        Ok(BruteForceSolver { input, primary_substations, lines, solution: None, optimal: false, limits: SolverLimits::default() })
+    }
+
+    fn get_solution(&self) -> Option<Solution> {
+        let (taps_positions, cuts) = self.solution.as_ref()?;
+        let mut answer = Solution::new(
+            &self.input,
+            self.primary_substations.iter().copied().zip(taps_positions.iter().copied()).collect(),
+            self.lines.iter().zip(cuts.iter()).map(|(line, &cut_i)| LineCut { line: line.clone(), cut_i }).collect(),
+            self.get_statistics(),
+        );
+        answer.optimal = self.optimal;
+        Some(answer)
+    }
+
+    fn solve(&mut self) -> Result<(), SolverError> {
+        self.solution = None;
+        let deadline = self.limits.start();
+        let line_endpoints = self.lines.iter().map(|line| (
+            self.primary_substations.binary_search(&line[0]).unwrap(),
+            self.primary_substations.binary_search(line.last().unwrap()).unwrap(),
+        )).collect_vec();
+        let mut best: Option<(TapValue, Vec<TapValue>, Vec<usize>)> = None;
+        let mut interruption: Option<SolverError> = None;
+        // Combinations are tried in lexicographic order, so the first optimal one
+        // is kept
+        for taps_positions in self.primary_substations.iter().map(|_| -10..=10 as TapValue).multi_cartesian_product() {
+            if let Err(error) = deadline.check("BruteForceSolver") {
+                interruption = Some(error);
+                break;
+            }
+            let objective_value = taps_positions.iter().map(|x| x.abs()).max().unwrap();
+            if best.as_ref().is_some_and(|x| x.0 <= objective_value) {
+                continue;
+            }
+            let cuts: Option<Vec<usize>> = self.lines
+                .iter()
+                .zip(line_endpoints.iter())
+                .map(|(line, &(left_i, right_i))| self.feasible_cut(line, taps_positions[left_i], taps_positions[right_i]))
+                .collect();
+            if let Some(cuts) = cuts {
+                best = Some((objective_value, taps_positions, cuts));
+            }
+        }
+        match (best, interruption) {
+            // The best configuration found before the interruption is kept
+            (Some((_, taps_positions, cuts)), interruption) => {
+                self.solution = Some((taps_positions, cuts));
+                self.optimal = interruption.is_none();
+                Ok(())
+            },
+            (None, Some(error)) => Err(error),
+            (None, None) => Err(SolverError::Infeasible { description: "BruteForceSolver. The problem instance is infeasible.".to_string(), vertex_ids: Vec::new() }),
+        }
+    }
+
+    fn set_limits(&mut self, limits: SolverLimits) -> Result<(), SolverError> {
+        self.limits = limits;
+        Ok(())
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crabnets::ImmutableGraphContainer;
+    use crate::{solver::{base_solver::BaseSolver, benchmark::{generate_distribution_grid, small_grid_parameters, GeneratorParameters, Topology}, errors::SolverError}, switch_selection_instance::SwitchSelectionInstance};
+    use super::{BruteForceSolver, MAX_PRIMARY_SUBSTATION_COUNT};
+
+    // Partial k-trees need more primary substations, so the DG-kernels are chains
+    fn random_instance(primary_substation_count: usize, seed: u64) -> SwitchSelectionInstance {
+        let parameters = GeneratorParameters { topology: Topology::RuralChain, ..small_grid_parameters(seed) };
+        SwitchSelectionInstance::new(generate_distribution_grid(2, primary_substation_count, &parameters, seed)).unwrap()
+    }
+
+    #[test]
+    fn solutions_pass_the_configuration_check() {
+        for seed in 0..20 {
+            let mut solver = BruteForceSolver::with_input(random_instance(3, seed)).unwrap();
+            if solver.solve().is_err() {
+                continue;
+            }
+            let solution = solver.get_solution().unwrap();
+            let mut grid = solver.input.unwrap().clone();
+            solution.render(&mut grid);
+            assert_eq!(SwitchSelectionInstance::new(grid).unwrap().check_configuration().unwrap(), solution.objective.value, "seed = {}", seed);
+        }
+    }
+
+    #[test]
+    fn large_instances_are_refused() {
+        assert!(matches!(BruteForceSolver::with_input(random_instance(MAX_PRIMARY_SUBSTATION_COUNT + 1, 0)), Err(SolverError::ResourceLimit { .. })));
+    }
+}
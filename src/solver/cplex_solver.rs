@@ -1,8 +1,8 @@
-use std::{collections::HashMap, pin::Pin, ptr::NonNull};
-use cplex_dynamic::{Constraint, ConstraintType, Env, Problem, ProblemType, Solution, Variable, VariableType, VariableValue, WeightedVariable};
+use std::{collections::HashMap, pin::Pin, ptr::NonNull, sync::mpsc::{self, RecvTimeoutError}, thread, time::Duration};
+use cplex_dynamic::{Constraint, ConstraintType, Env, Problem, ProblemType, Solution as CPLEXSolution, Variable, VariableType, VariableValue, WeightedVariable};
 use crabnets::{BasicImmutableGraph, BasicMutableGraph, ImmutableGraphContainer};
-use crate::switch_selection_instance::{SwitchSelectionGraph, SwitchSelectionInstance};
-use super::{base_solver::*, errors::SolverError};
+use crate::switch_selection_instance::SwitchSelectionInstance;
+use super::{base_solver::*, errors::{GraphError, SolverError}, solution::Solution};
 
 
 
@@ -12,11 +12,24 @@ macro_rules! cplex_unwrap {
     ($expr: expr) => {
         match $expr {
             Ok(value) => value,
-            Err(error) => return Err(SolverError::from_string(format!("CPLEXSolver. {}", error))),
+            Err(error) => return Err(SolverError::BackendFailed { description: "CPLEXSolver. CPLEX has reported an error.".to_string(), source: Box::from(error.to_string()) }),
         }
     };
 }
 
+// CPLEX error code of a problem without a solution
+const CPXERR_NO_SOLN: i32 = 1217;
+// How often the limits are checked while CPLEX runs on a separate thread
+const LIMITS_CHECK_INTERVAL: Duration = Duration::from_millis(10);
+
+// Extracts the error code from a CPLEX error message  of  the  form  "CPLEX
+// Error  1217: No solution exists.", so that numbers in the text of the
+// message aren't mistaken for it
+fn cplex_error_code(message: &str) -> Option<i32> {
+    let (head, _) = message.split_once(':')?;
+    head.split_whitespace().last()?.parse().ok()
+}
+
 
 
 pub struct CPLEXSolverCore<'a> {
@@ -25,6 +38,17 @@ pub struct CPLEXSolverCore<'a> {
     variables: HashMap<String, usize>,
     problem: Option<Problem<'a>>,
     env: Env,
+    limits: SolverLimits,
+}
+
+
+
+// CPLEXSolverCore::CPLEXSolverCore
+impl<'a> CPLEXSolverCore<'a> {
+    // CPLEX can only be used if its dynamic library can be loaded
+    pub fn is_available() -> bool {
+        Env::new().is_ok()
+    }
 }
 
 
@@ -35,6 +59,7 @@ pub type CPLEXSolver<'a> = Pin<Box<CPLEXSolverCore<'a>>>;
 
 trait CPLEXSolverTools<'a> {
     fn get_problem_mut(&mut self) -> &mut Problem<'a>;
+    fn read_solution(&self, solution: &CPLEXSolution) -> Result<Solution, GraphError>;
 }
 
 
@@ -46,12 +71,57 @@ impl<'a> CPLEXSolverTools<'a> for CPLEXSolver<'a> {
             self.as_mut().get_unchecked_mut().problem.as_mut().unwrap()
         }
     }
+
+    // Reads the tap positions and the opened switches from the values of the
+    // variables found by CPLEX
+    fn read_solution(&self, solution: &CPLEXSolution) -> Result<Solution, GraphError> {
+        let mut answer = self.input.unwrap().clone();
+        for primary_substation_id in self.input.dg_kernel_for_switch_selection().iter_v() {
+            for tap_position in -10..=10i8 {
+                if let VariableValue::Integer(1) = solution.variables[self.variables[&format!("tap({},{})", tap_position, primary_substation_id)]] {
+                    answer.v_attrs_mut(&primary_substation_id).unwrap().tap_position = Some(tap_position);
+                }
+            }
+        }
+        for edge in self.input.iter_e() {
+            let endpoints = self.input.e_attrs(&edge.id1, &edge.id2, &0).unwrap().line_endpoints.unwrap();
+            if self.input.v_attrs(&edge.id1).unwrap().tap_position.is_some() {
+                if self.input.v_attrs(&edge.id2).unwrap().tap_position.is_some() {
+                    answer.e_attrs_mut(&edge.id1, &edge.id2, &0).unwrap().switch = true;
+                    continue;
+                }
+                if let VariableValue::Integer(value) = solution.variables[self.variables[&format!("part({})", edge.id2)]] {
+                    answer.e_attrs_mut(&edge.id1, &edge.id2, &0).unwrap().switch = endpoints.0 == edge.id1 && value == 1 || endpoints.1 == edge.id1 && value == 0;
+                }
+                continue;
+            }
+            if self.input.v_attrs(&edge.id2).unwrap().tap_position.is_some() {
+                if let VariableValue::Integer(value) = solution.variables[self.variables[&format!("part({})", edge.id1)]] {
+                    answer.e_attrs_mut(&edge.id1, &edge.id2, &0).unwrap().switch = endpoints.0 == edge.id2 && value == 1 || endpoints.1 == edge.id2 && value == 0;
+                }
+                continue;
+            }
+            if let VariableValue::Integer(value1) = solution.variables[self.variables[&format!("part({})", edge.id1)]] {
+                if let VariableValue::Integer(value2) = solution.variables[self.variables[&format!("part({})", edge.id2)]] {
+                    answer.e_attrs_mut(&edge.id1, &edge.id2, &0).unwrap().switch = value1 != value2;
+                }
+            }
+        }
+        Solution::from_grid(&self.input, &answer, self.get_statistics())
+    }
 }
 
 // CPLEXSolver::BaseSolver
 impl<'a> BaseSolver for CPLEXSolver<'a> {
     fn with_input(input: SwitchSelectionInstance) -> Result<Self, SolverError> {
-        let mut solver: CPLEXSolver = Box::pin(CPLEXSolverCore { input: input.clone(), env: cplex_unwrap!(Env::new()), variables: HashMap::new(), problem: None, solution: None });
+        let env = match Env::new() {
+            Ok(value) => value,
+            Err(error) => return Err(SolverError::BackendUnavailable {
+                description: "CPLEXSolver. Failed to load CPLEX.".to_string(),
+                source: Some(Box::from(error.to_string())),
+            }),
+        };
+        let mut solver: CPLEXSolver = Box::pin(CPLEXSolverCore { input: input.clone(), env, variables: HashMap::new(), problem: None, solution: None, limits: SolverLimits::default() });
         unsafe {
             // Create a problem instance
             solver.as_mut().get_unchecked_mut().problem = Some(cplex_unwrap!(Problem::new(NonNull::from(&solver.as_ref().env).as_ref(), "name")));
@@ -110,7 +180,7 @@ impl<'a> BaseSolver for CPLEXSolver<'a> {
             {
                 // Possible values of the square voltage at a primary substation.
                 // Tap positions are: T = {-10, ..., 10}.
-                // Base voltage: B = {1 + 0.1 * t | t \in T}.
+                // Base voltage: B = {1 + 0.01 * t | t \in T}.
                 // Square base voltage: {u² | u \in B}; for each t \in T the  squared  base
                 // voltage corresponding to t is BASE_VOLTAGE_SQ[t + 10].
                 const BASE_VOLTAGE_SQ: [f64; 21] = [0.81, 0.8281, 0.8464, 0.8649, 0.8836, 0.9025, 0.9216, 0.9409, 0.9604, 0.9801, 1.0,
@@ -524,47 +594,76 @@ impl<'a> BaseSolver for CPLEXSolver<'a> {
         Ok(solver)
     }
 
-    fn get_solution(&self) -> Option<(SwitchSelectionGraph, TapValue)> {
-        if self.solution.is_none() {
-            return None;
-        }
-        let mut answer = self.input.unwrap().clone();
-        for primary_substation_id in self.input.dg_kernel_for_switch_selection().iter_v() {
-            for tap_position in -10..10i8 {
-                if let VariableValue::Integer(1) = self.solution.as_ref().unwrap().variables[self.variables[&format!("tap({},{})", tap_position, primary_substation_id)]] {
-                    answer.v_attrs_mut(&primary_substation_id).unwrap().tap_position = Some(tap_position);
+    fn get_solution(&self) -> Option<Solution> {
+        self.solution.clone()
+    }
+
+    // cplex_dynamic doesn't expose the time limit parameter of CPLEX
+    // (CPX_PARAM_TILIM), and the call into CPLEX can't be interrupted. With
+    // limits, another CPLEXSolver builds and solves the problem on a  separate
+    // thread, and solve fails with SolverError::Timeout as soon as the limits
+    // are reached. The thread is left running in the background until  CPLEX
+    // finishes, and its result is discarded.
+    fn solve(&mut self) -> Result<(), SolverError> {
+        self.solution = None;
+        if !self.limits.is_unlimited() {
+            let deadline = self.limits.start();
+            deadline.check("CPLEXSolver")?;
+            let input = self.input.clone();
+            let (tx, rx) = mpsc::channel();
+            thread::spawn(move || {
+                let result = CPLEXSolver::with_input(input).and_then(|mut solver| {
+                    solver.solve()?;
+                    solver.try_get_solution()
+                });
+                tx.send(result).unwrap_or(());
+            });
+            loop {
+                match rx.recv_timeout(LIMITS_CHECK_INTERVAL) {
+                    Ok(result) => {
+                        self.solution = Some(result?);
+                        return Ok(());
+                    },
+                    Err(RecvTimeoutError::Timeout) => deadline.check("CPLEXSolver")?,
+                    Err(RecvTimeoutError::Disconnected) => return Err(SolverError::BackendFailed {
+                        description: "CPLEXSolver. CPLEX has stopped without a result.".to_string(),
+                        source: Box::from("The thread running CPLEX has panicked."),
+                    }),
                 }
             }
         }
-        for edge in self.input.iter_e() {
-            let endpoints = self.input.e_attrs(&edge.id1, &edge.id2, &0).unwrap().line_endpoints.unwrap();
-            if self.input.v_attrs(&edge.id1).unwrap().tap_position.is_some() {
-                if self.input.v_attrs(&edge.id2).unwrap().tap_position.is_some() {
-                    answer.e_attrs_mut(&edge.id1, &edge.id2, &0).unwrap().switch = true;
-                    continue;
-                }
-                if let VariableValue::Integer(value) = self.solution.as_ref().unwrap().variables[self.variables[&format!("part({})", edge.id2)]] {
-                    answer.e_attrs_mut(&edge.id1, &edge.id2, &0).unwrap().switch = endpoints.0 == edge.id1 && value == 1 || endpoints.1 == edge.id1 && value == 0;
-                }
-                continue;
-            }
-            if self.input.v_attrs(&edge.id2).unwrap().tap_position.is_some() {
-                if let VariableValue::Integer(value) = self.solution.as_ref().unwrap().variables[self.variables[&format!("part({})", edge.id1)]] {
-                    answer.e_attrs_mut(&edge.id1, &edge.id2, &0).unwrap().switch = endpoints.0 == edge.id2 && value == 1 || endpoints.1 == edge.id2 && value == 0;
-                }
-                continue;
-            }
-            if let VariableValue::Integer(value1) = self.solution.as_ref().unwrap().variables[self.variables[&format!("part({})", edge.id1)]] {
-                if let VariableValue::Integer(value2) = self.solution.as_ref().unwrap().variables[self.variables[&format!("part({})", edge.id2)]] {
-                    answer.e_attrs_mut(&edge.id1, &edge.id2, &0).unwrap().switch = value1 != value2;
-                }
-            }
+        match self.get_problem_mut().solve(ProblemType::MixedInteger) {
+            Ok(value) => match self.read_solution(&value) {
+                Ok(solution) => self.solution = Some(solution),
+                Err(error) => return Err(SolverError::BackendFailed { description: "CPLEXSolver. CPLEX has returned an invalid configuration.".to_string(), source: Box::new(error) }),
+            },
+            // CPLEX reports infeasibility by failing to return a solution
+            Err(error) if cplex_error_code(&error.to_string()) == Some(CPXERR_NO_SOLN) => return Err(SolverError::Infeasible {
+                description: "CPLEXSolver. The problem instance is infeasible.".to_string(),
+                vertex_ids: Vec::new(),
+            }),
+            Err(error) => return Err(SolverError::BackendFailed { description: "CPLEXSolver. CPLEX has reported an error.".to_string(), source: Box::from(error.to_string()) }),
         }
-        Some((answer, self.solution.as_ref().unwrap().objective as TapValue))
+        Ok(())
     }
 
-    fn solve(&mut self) -> Result<(), SolverError> {
-        self.solution = Some(cplex_unwrap!(self.get_problem_mut().solve(ProblemType::MixedInteger)));
+    fn set_limits(&mut self, limits: SolverLimits) -> Result<(), SolverError> {
+        self.limits = limits;
         Ok(())
     }
 }
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::{cplex_error_code, CPXERR_NO_SOLN};
+
+    #[test]
+    fn error_codes_are_matched_exactly() {
+        assert_eq!(cplex_error_code("CPLEX Error  1217: No solution exists."), Some(CPXERR_NO_SOLN));
+        assert_eq!(cplex_error_code("CPLEX Error  12170: Unknown error."), Some(12170));
+        assert_eq!(cplex_error_code("CPLEX Error  3019: Failure to solve MIP subproblem 1217."), Some(3019));
+        assert_eq!(cplex_error_code("No solution exists (1217)"), None);
+    }
+}
@@ -0,0 +1,233 @@
+// Randomised differential tests: every available solver is run on hundreds of
+// small generated grids, and the solvers must agree on the feasibility and on
+// the optimal objective value of each of them. Configurations returned by the
+// solvers are verified by a voltage and radiality check that works  on  the
+// whole grid and shares no code with SwitchSelectionInstance.
+use std::{collections::{HashMap, VecDeque}, ops::Range, sync::{atomic::{AtomicBool, Ordering}, Arc}, thread, time::{Duration, Instant}};
+use crabnets::{BasicImmutableGraph, BasicMutableGraph, ImmutableGraphContainer};
+use itertools::Itertools;
+use crate::switch_selection_instance::{SwitchSelectionGraph, SwitchSelectionInstance};
+use super::{base_solver::{BaseSolver, SolverLimits, TapValue}, benchmark::{generate_distribution_grid, small_grid_parameters, FeederModel, GeneratorParameters, LoadModel}, brute_force_solver::BruteForceSolver, cplex_solver::CPLEXSolver, errors::SolverError, registry::SolverKind, solution::{LineCut, Solution}, tree_decomposition_solver::{NiceTreeDecompositionSolver, TreeDecompositionSolver}};
+
+// Number of grids with 3 primary substations
+const SMALL_INSTANCE_COUNT: u64 = 240;
+// Number of grids with 4 primary substations, which take BruteForceSolver  21
+// times longer. They are only checked with --ignored.
+const LARGE_INSTANCE_COUNT: u64 = 24;
+
+// Generates the grid of the given seed. The seed determines the topology, the
+// feeder and load models and the treewidth of the DG-kernel. Every fifth grid
+// has heavy loads, so that some of the grids are infeasible.
+fn random_instance(seed: u64) -> SwitchSelectionInstance {
+    let heavy_loads = seed % 5 == 4;
+    let parameters = GeneratorParameters {
+        feeder_model: if seed / 2 % 2 == 0 { FeederModel::Uniform } else { FeederModel::CableTypes },
+        load_model: if seed / 4 % 2 == 0 || heavy_loads { LoadModel::Uniform } else { LoadModel::Profiles },
+        p: if heavy_loads { (1.0, 4.0) } else { (-0.3, 0.3) },
+        q: if heavy_loads { (0.0, 2.0) } else { (-0.3, 0.3) },
+        ..small_grid_parameters(seed)
+    };
+    let treewidth = 1 + (seed / 8 % 2) as usize;
+    let primary_substation_count = if seed < SMALL_INSTANCE_COUNT { 3 } else { 4 };
+    SwitchSelectionInstance::new(generate_distribution_grid(treewidth, primary_substation_count, &parameters, seed)).unwrap()
+}
+
+fn solve_with<S: BaseSolver>(input: SwitchSelectionInstance, limits: SolverLimits) -> Result<Solution, SolverError> {
+    let mut solver = S::with_input(input)?;
+    solver.set_limits(limits)?;
+    solver.solve()?;
+    solver.try_get_solution()
+}
+
+fn solve_limited(kind: SolverKind, input: SwitchSelectionInstance, limits: SolverLimits) -> Result<Solution, SolverError> {
+    match kind {
+        SolverKind::TreeDecomposition => solve_with::<TreeDecompositionSolver>(input, limits),
+        SolverKind::NiceTreeDecomposition => solve_with::<NiceTreeDecompositionSolver>(input, limits),
+        SolverKind::CPLEX => solve_with::<CPLEXSolver>(input, limits),
+        SolverKind::BruteForce => solve_with::<BruteForceSolver>(input, limits),
+    }
+}
+
+// Returns None if the solver found the problem instance infeasible. Any other
+// error fails the test.
+fn solve(kind: SolverKind, input: SwitchSelectionInstance) -> Option<Solution> {
+    match solve_limited(kind, input, SolverLimits::default()) {
+        Ok(value) => Some(value),
+        Err(SolverError::Infeasible { .. }) => None,
+        Err(error) => panic!("{}", error),
+    }
+}
+
+// Checks that the configuration stored in the grid is feasible and returns its
+// objective value with the  squared  voltage  of  each  substation.  The  grid
+// without its opened switches must be a forest each  tree  of  which  contains
+// exactly  one  primary  substation.  Each  tree  is  rooted  at  its  primary
+// substation, and the squared  voltage  of  each  substation  is  the  squared
+// voltage of its parent plus x * Q - r * P, where P and Q are the total  loads
+// of the subtree of the substation and r and x are the impedance of  the  edge
+// to the parent.
+fn check_configuration(grid: &SwitchSelectionGraph) -> Result<(TapValue, HashMap<usize, f64>), String> {
+    // Tolerance for the comparison of voltages, loose enough for CPLEX
+    const EPSILON: f64 = 1e-6;
+    let primary_substations = grid.iter_v().filter(|x| grid.v_attrs(x).unwrap().tap_position.is_some()).sorted().collect_vec();
+    let mut objective_value: TapValue = 0;
+    let mut all_voltages_sq: HashMap<usize, f64> = HashMap::new();
+    // Parent of each substation reached so far
+    let mut parents: HashMap<usize, Option<usize>> = HashMap::new();
+    for primary_substation_id in primary_substations {
+        let tap_position = grid.v_attrs(&primary_substation_id).unwrap().tap_position.unwrap();
+        if !(-10..=10).contains(&tap_position) {
+            return Err(format!("Primary substation {} has tap position {}.", primary_substation_id, tap_position));
+        }
+        objective_value = objective_value.max(tap_position.abs());
+        if parents.contains_key(&primary_substation_id) {
+            return Err(format!("Primary substation {} is fed by another primary substation.", primary_substation_id));
+        }
+        // Breadth-first search over closed switches
+        parents.insert(primary_substation_id, None);
+        let mut order = Vec::new();
+        let mut queue = VecDeque::from([primary_substation_id]);
+        while let Some(substation_id) = queue.pop_front() {
+            order.push(substation_id);
+            for adjacent_id in grid.iter_adjacent(&substation_id).unwrap().sorted() {
+                if grid.e_attrs(&substation_id, &adjacent_id, &0).unwrap().switch || parents[&substation_id] == Some(adjacent_id) {
+                    continue;
+                }
+                if parents.contains_key(&adjacent_id) {
+                    return Err(format!("Substations {} and {} are connected by a cycle or by two primary substations.", substation_id, adjacent_id));
+                }
+                parents.insert(adjacent_id, Some(substation_id));
+                queue.push_back(adjacent_id);
+            }
+        }
+        // Total loads of the subtrees, computed from the leaves up
+        let mut loads: HashMap<usize, (f64, f64)> = HashMap::new();
+        for &substation_id in order.iter().rev() {
+            let attributes = grid.v_attrs(&substation_id).unwrap();
+            let (p, q) = loads.get(&substation_id).copied().unwrap_or((0.0, 0.0));
+            let subtree_load = (p + attributes.p, q + attributes.q);
+            loads.insert(substation_id, subtree_load);
+            if let Some(parent_id) = parents[&substation_id] {
+                let parent_load = loads.entry(parent_id).or_insert((0.0, 0.0));
+                parent_load.0 += subtree_load.0;
+                parent_load.1 += subtree_load.1;
+            }
+        }
+        // Squared voltages, computed from the root down
+        let mut voltages_sq: HashMap<usize, f64> = HashMap::from([(primary_substation_id, (1.0 + 0.01 * tap_position as f64).powi(2))]);
+        for &substation_id in order.iter().skip(1) {
+            let parent_id = parents[&substation_id].unwrap();
+            let edge = grid.e_attrs(&parent_id, &substation_id, &0).unwrap();
+            let (p, q) = loads[&substation_id];
+            let voltage_sq = voltages_sq[&parent_id] + edge.x * q - edge.r * p;
+            if voltage_sq < 0.81 - EPSILON || voltage_sq > 1.21 + EPSILON {
+                return Err(format!("Squared voltage {} at substation {} is not in [0.81, 1.21].", voltage_sq, substation_id));
+            }
+            voltages_sq.insert(substation_id, voltage_sq);
+        }
+        all_voltages_sq.extend(voltages_sq);
+    }
+    if parents.len() != grid.count_v() {
+        return Err(format!("{} substations are not fed by any primary substation.", grid.count_v() - parents.len()));
+    }
+    Ok((objective_value, all_voltages_sq))
+}
+
+fn check_solvers_agree(seeds: Range<u64>) {
+    let solver_kinds = SolverKind::ALL.into_iter().filter(|x| x.is_available()).collect_vec();
+    for seed in seeds {
+        let instance = random_instance(seed);
+        let objective_values = solver_kinds.iter().map(|&kind| {
+            let solution = solve(kind, instance.clone());
+            if let Some(solution) = &solution {
+                let mut grid = instance.unwrap().clone();
+                solution.render(&mut grid);
+                let (objective_value, voltages_sq) = check_configuration(&grid).unwrap_or_else(|error| panic!("seed = {}, solver = {}: {}", seed, kind, error));
+                assert_eq!(objective_value, solution.objective.value, "seed = {}, solver = {}", seed, kind);
+                // The voltages reported by the solution are computed by SwitchSelectionInstance,
+                // so they are compared with the independent ones
+                assert_eq!(solution.voltages_sq.len(), voltages_sq.len(), "seed = {}, solver = {}", seed, kind);
+                for (substation_id, voltage_sq) in solution.voltages_sq.iter() {
+                    assert!((voltage_sq - voltages_sq[substation_id]).abs() < 1e-9, "seed = {}, solver = {}, substation = {}", seed, kind, substation_id);
+                }
+            }
+            solution.map(|x| x.objective.value)
+        }).collect_vec();
+        for (kind, objective_value) in solver_kinds.iter().zip(objective_values.iter()).skip(1) {
+            assert_eq!(*objective_value, objective_values[0], "seed = {}, {} disagrees with {}", seed, kind, solver_kinds[0]);
+        }
+    }
+}
+
+#[test]
+fn solvers_agree_on_random_grids() {
+    check_solvers_agree(0..SMALL_INSTANCE_COUNT);
+}
+
+#[test]
+#[ignore]
+fn solvers_agree_on_random_large_grids() {
+    check_solvers_agree(SMALL_INSTANCE_COUNT..(SMALL_INSTANCE_COUNT + LARGE_INSTANCE_COUNT));
+}
+
+#[test]
+fn independent_check_rejects_broken_configurations() {
+    let (instance, solution) = (0..SMALL_INSTANCE_COUNT)
+        .find_map(|seed| {
+            let instance = random_instance(seed);
+            solve(SolverKind::BruteForce, instance.clone()).map(|x| (instance, x))
+        })
+        .unwrap();
+    let mut grid = instance.unwrap().clone();
+    solution.render(&mut grid);
+    let LineCut { line, cut_i } = solution.cuts.into_iter().max_by_key(|x| x.line.len()).unwrap();
+    // Closing the opened switch connects two primary substations
+    grid.e_attrs_mut(&line[cut_i], &line[cut_i + 1], &0).unwrap().switch = false;
+    assert!(check_configuration(&grid).is_err());
+    // Opening every switch of the line leaves its secondary substations unfed
+    for i in 0..(line.len() - 1) {
+        grid.e_attrs_mut(&line[i], &line[i + 1], &0).unwrap().switch = true;
+    }
+    assert!(check_configuration(&grid).is_err());
+}
+
+#[test]
+fn solvers_give_up_at_their_limits() {
+    let cancelled = SolverLimits { time_limit: None, cancellation_token: Some(Arc::new(AtomicBool::new(true))) };
+    let expired = SolverLimits { time_limit: Some(Duration::ZERO), cancellation_token: None };
+    // Limits that are never reached don't change the solution
+    let unreached = SolverLimits { time_limit: Some(Duration::from_secs(3600)), cancellation_token: Some(Arc::new(AtomicBool::new(false))) };
+    for kind in SolverKind::ALL.into_iter().filter(|x| x.is_available()) {
+        for limits in [&cancelled, &expired] {
+            let result = solve_limited(kind, random_instance(0), limits.clone());
+            assert!(matches!(result, Err(SolverError::Timeout { .. })), "solver = {}, limits = {:?}", kind, limits);
+        }
+        let solution = solve_limited(kind, random_instance(0), unreached.clone()).ok();
+        assert_eq!(solution.as_ref().map(|x| x.objective.value), solve(kind, random_instance(0)).map(|x| x.objective.value), "solver = {}", kind);
+        assert!(solution.is_none_or(|x| x.optimal), "solver = {}", kind);
+    }
+}
+
+#[test]
+fn cplex_solver_is_cancelled_while_cplex_runs() {
+    if !SolverKind::CPLEX.is_available() {
+        return;
+    }
+    // The token is set from another thread, usually while CPLEX is  running,
+    // and solve returns soon after without waiting for CPLEX
+    let cancellation_token = Arc::new(AtomicBool::new(false));
+    let limits = SolverLimits { time_limit: None, cancellation_token: Some(cancellation_token.clone()) };
+    let instance = random_instance(SMALL_INSTANCE_COUNT);
+    let canceller = thread::spawn(move || cancellation_token.store(true, Ordering::Relaxed));
+    let begin_time = Instant::now();
+    let result = solve_limited(SolverKind::CPLEX, instance.clone(), limits);
+    canceller.join().unwrap();
+    match result {
+        Err(SolverError::Timeout { .. }) => assert!(begin_time.elapsed() < Duration::from_secs(1)),
+        // CPLEX may finish before the token is checked, but then it must  have
+        // found the optimal configuration
+        Ok(solution) => assert_eq!(Some(solution.objective.value), solve(SolverKind::BruteForce, instance).map(|x| x.objective.value)),
+        Err(SolverError::Infeasible { .. }) => assert!(solve(SolverKind::BruteForce, instance).is_none()),
+        Err(error) => panic!("{}", error),
+    }
+}
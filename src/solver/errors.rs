@@ -4,59 +4,156 @@ use std::{error::Error, fmt::Display};
 
 
 
+// Underlying error that caused another one, e.g. an error of the  OS  or  of
+// the MILP backend
+pub type ErrorSource = Box<dyn Error + Send + Sync>;
+
+
+
 #[derive(Debug)]
-pub struct GraphError {
-    description: String,
+pub enum GraphError {
+    // The grid or a tree decomposition has an invalid structure. vertex_ids are
+    // the substations (or bags) the defect was found at.
+    InvalidTopology { description: String, vertex_ids: Vec<usize> },
+    // An attribute of a substation or a feeder has no usable value
+    MissingAttribute { description: String, vertex_ids: Vec<usize> },
+    // The tap positions and the opened switches stored in the grid don't form a
+    // feasible configuration
+    InvalidConfiguration { description: String, vertex_ids: Vec<usize> },
+    // A file doesn't follow its format
+    InvalidFormat { description: String },
+    // A file can't be read or written
+    Io { description: String, source: ErrorSource },
 }
 
 // GraphError::GraphError
 impl GraphError {
-    #[inline]
-    pub fn from_str(description: &str) -> Self {
-        GraphError { description: description.to_string() }
-    }
-
-    #[inline]
-    pub fn from_string(description: String) -> Self {
-        GraphError { description }
+    // Substations (or bags) the error concerns, if it can be attributed to any
+    pub fn vertex_ids(&self) -> &[usize] {
+        match self {
+            GraphError::InvalidTopology { vertex_ids, .. } | GraphError::MissingAttribute { vertex_ids, .. } | GraphError::InvalidConfiguration { vertex_ids, .. } => vertex_ids,
+            GraphError::InvalidFormat { .. } | GraphError::Io { .. } => &[],
+        }
     }
 }
 
 // GraphError::Error
-impl Error for GraphError {}
+impl Error for GraphError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            GraphError::Io { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
 
 // GraphError::Display
 impl Display for GraphError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.description)
+        match self {
+            GraphError::InvalidTopology { description, .. }
+            | GraphError::MissingAttribute { description, .. }
+            | GraphError::InvalidConfiguration { description, .. }
+            | GraphError::InvalidFormat { description }
+            | GraphError::Io { description, .. } => write!(f, "{}", description),
+        }
     }
 }
 
 
 
 #[derive(Debug)]
-pub struct SolverError {
-    description: String,
+pub enum SolverError {
+    // The problem instance has no feasible configuration. vertex_ids  are  the
+    // primary substations whose tap positions can't be chosen consistently, if
+    // the solver can narrow them down.
+    Infeasible { description: String, vertex_ids: Vec<usize> },
+    // Solving the problem instance would exceed the memory budget or the limits
+    // of the solver
+    ResourceLimit { description: String },
+    // The backend of the solver, e.g. the CPLEX library or its licence, can't be
+    // loaded
+    BackendUnavailable { description: String, source: Option<ErrorSource> },
+    // The backend of the solver has reported an error
+    BackendFailed { description: String, source: ErrorSource },
+    // The tree decomposition of the DG-kernel can't be computed or imported
+    TreeDecompositionFailed { description: String, source: GraphError },
+    // A file can't be written
+    Io { description: String, source: ErrorSource },
+    // Solvers have found different optimal values for the same problem instance
+    InconsistentResults { description: String },
+    // The solver has exceeded its time limit or has been cancelled before it
+    // found any feasible configuration
+    Timeout { description: String },
+    // The options of the solver are inconsistent or not supported by it
+    InvalidOptions { description: String },
 }
 
 // SolverError::SolverError
 impl SolverError {
+    // Substations the error concerns, if it can be attributed to any
+    pub fn vertex_ids(&self) -> &[usize] {
+        match self {
+            SolverError::Infeasible { vertex_ids, .. } => vertex_ids,
+            SolverError::TreeDecompositionFailed { source, .. } => source.vertex_ids(),
+            _ => &[],
+        }
+    }
+}
+
+// SolverError::Error
+impl Error for SolverError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SolverError::TreeDecompositionFailed { source, .. } => Some(source),
+            SolverError::BackendUnavailable { source: Some(source), .. } | SolverError::BackendFailed { source, .. } | SolverError::Io { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+// SolverError::Display
+impl Display for SolverError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SolverError::Infeasible { description, .. }
+            | SolverError::ResourceLimit { description }
+            | SolverError::BackendUnavailable { description, .. }
+            | SolverError::BackendFailed { description, .. }
+            | SolverError::TreeDecompositionFailed { description, .. }
+            | SolverError::Io { description, .. }
+            | SolverError::InconsistentResults { description }
+            | SolverError::Timeout { description }
+            | SolverError::InvalidOptions { description } => write!(f, "{}", description),
+        }
+    }
+}
+
+
+
+#[derive(Debug)]
+pub struct UsageError {
+    description: String,
+}
+
+// UsageError::UsageError
+impl UsageError {
     #[inline]
     pub fn from_str(description: &str) -> Self {
-        SolverError { description: description.to_string() }
+        UsageError { description: description.to_string() }
     }
 
     #[inline]
     pub fn from_string(description: String) -> Self {
-        SolverError { description }
+        UsageError { description }
     }
 }
 
-// SolverError::Error
-impl Error for SolverError {}
+// UsageError::Error
+impl Error for UsageError {}
 
-// SolverError::Display
-impl Display for SolverError {
+// UsageError::Display
+impl Display for UsageError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.description)
     }
@@ -1,5 +1,11 @@
 pub mod base_solver;
 pub mod benchmark;
+pub mod benchmark_results;
+pub mod brute_force_solver;
 pub mod cplex_solver;
+#[cfg(test)]
+mod differential_tests;
 pub mod tree_decomposition_solver;
 pub mod errors;
+pub mod registry;
+pub mod solution;
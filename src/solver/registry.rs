@@ -0,0 +1,87 @@
+use std::{fmt::Display, str::FromStr};
+use crate::switch_selection_instance::SwitchSelectionInstance;
+use super::{base_solver::{BaseSolver, SolverStatistics, TapValue}, benchmark::{peak_resident_memory, reset_peak_resident_memory, timeit, TimingStatistics}, brute_force_solver::BruteForceSolver, cplex_solver::{CPLEXSolver, CPLEXSolverCore}, errors::{SolverError, UsageError}, tree_decomposition_solver::{NiceTreeDecompositionSolver, TreeDecompositionSolver}};
+
+
+
+
+
+// Results of repeated runs of a solver on the same problem instance
+#[derive(Clone, Debug)]
+pub struct TimedRun {
+    pub timing: TimingStatistics,
+    pub objective_value: TapValue,
+    pub statistics: SolverStatistics,
+    // Peak resident set size in KiB during the runs
+    pub peak_memory: Option<u64>,
+}
+
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SolverKind {
+    TreeDecomposition,
+    NiceTreeDecomposition,
+    CPLEX,
+    BruteForce,
+}
+
+// SolverKind::SolverKind
+impl SolverKind {
+    pub const ALL: [SolverKind; 4] = [SolverKind::TreeDecomposition, SolverKind::NiceTreeDecomposition, SolverKind::CPLEX, SolverKind::BruteForce];
+
+    // Whether the backend of the solver can be used on this machine
+    pub fn is_available(&self) -> bool {
+        match self {
+            SolverKind::TreeDecomposition | SolverKind::NiceTreeDecomposition | SolverKind::BruteForce => true,
+            SolverKind::CPLEX => CPLEXSolverCore::is_available(),
+        }
+    }
+
+    // Solves the instance repeat times, measures  the  last  repeat  -  ignore
+    // runs and returns the timing together with the optimal objective value.
+    pub fn timeit(&self, input: SwitchSelectionInstance, repeat: usize, ignore: usize) -> Result<TimedRun, SolverError> {
+        match self {
+            SolverKind::TreeDecomposition => timeit_solver::<TreeDecompositionSolver>(input, repeat, ignore),
+            SolverKind::NiceTreeDecomposition => timeit_solver::<NiceTreeDecompositionSolver>(input, repeat, ignore),
+            SolverKind::CPLEX => timeit_solver::<CPLEXSolver>(input, repeat, ignore),
+            SolverKind::BruteForce => timeit_solver::<BruteForceSolver>(input, repeat, ignore),
+        }
+    }
+}
+
+// SolverKind::FromStr
+impl FromStr for SolverKind {
+    type Err = UsageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "TreeDecompositionSolver" => Ok(SolverKind::TreeDecomposition),
+            "NiceTreeDecompositionSolver" => Ok(SolverKind::NiceTreeDecomposition),
+            "CPLEXSolver" => Ok(SolverKind::CPLEX),
+            "BruteForceSolver" => Ok(SolverKind::BruteForce),
+            _ => Err(UsageError::from_string(format!("Unknown solver {}.", s))),
+        }
+    }
+}
+
+// SolverKind::Display
+impl Display for SolverKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SolverKind::TreeDecomposition => write!(f, "TreeDecompositionSolver"),
+            SolverKind::NiceTreeDecomposition => write!(f, "NiceTreeDecompositionSolver"),
+            SolverKind::CPLEX => write!(f, "CPLEXSolver"),
+            SolverKind::BruteForce => write!(f, "BruteForceSolver"),
+        }
+    }
+}
+
+
+
+fn timeit_solver<S: BaseSolver>(input: SwitchSelectionInstance, repeat: usize, ignore: usize) -> Result<TimedRun, SolverError> {
+    reset_peak_resident_memory();
+    let mut solver: S = S::with_input(input)?;
+    let timing = timeit(&mut solver, repeat, ignore)?;
+    Ok(TimedRun { timing, objective_value: solver.try_get_solution()?.objective.value, statistics: solver.get_statistics(), peak_memory: peak_resident_memory() })
+}
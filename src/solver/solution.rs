@@ -0,0 +1,150 @@
+use std::collections::BTreeMap;
+use crabnets::{BasicImmutableGraph, BasicMutableGraph};
+use itertools::Itertools;
+use crate::{switch_selection_instance::{SwitchSelectionGraph, SwitchSelectionInstance}, tree_decomposition::TreeDecomposition};
+use super::{base_solver::{SolverStatistics, TapValue, BASE_VOLTAGE_SQ}, benchmark::TimingStatistics, errors::GraphError};
+
+
+
+
+
+// Opened switch of a line
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LineCut {
+    // Substations of the line as returned by SwitchSelectionInstance::lines
+    pub line: Vec<usize>,
+    // The switch on the edge (line[cut_i], line[cut_i + 1]) is opened
+    pub cut_i: usize,
+}
+
+// LineCut::LineCut
+impl LineCut {
+    pub fn opened_edge(&self) -> (usize, usize) {
+        (self.line[self.cut_i], self.line[self.cut_i + 1])
+    }
+}
+
+
+
+// How the objective value of a solution comes about
+#[derive(Clone, Debug)]
+pub struct ObjectiveBreakdown {
+    // Largest absolute tap position over all primary substations
+    pub value: TapValue,
+    // Primary substations whose absolute tap position equals the value
+    pub critical_primary_substations: Vec<usize>,
+    // Smallest and largest squared voltages over all substations:
+    // (substation ID, squared voltage)
+    pub min_voltage_sq: (usize, f64),
+    pub max_voltage_sq: (usize, f64),
+}
+
+
+
+// Configuration found by a solver: the tap positions of the primary substations
+// and the opened switch of each line
+#[derive(Clone)]
+pub struct Solution {
+    pub tap_positions: BTreeMap<usize, TapValue>,
+    // Cuts of the lines in the order of SwitchSelectionInstance::lines
+    pub cuts: Vec<LineCut>,
+    // Squared voltage of each substation in the configuration
+    pub voltages_sq: BTreeMap<usize, f64>,
+    pub objective: ObjectiveBreakdown,
+    // False if the solver reached its limits before it could prove  that  the
+    // configuration is optimal, see SolverLimits
+    pub optimal: bool,
+    pub statistics: SolverStatistics,
+    // Timing of the runs of the solver, filled in by solve
+    pub timing: Option<TimingStatistics>,
+    // Tree decomposition of the DG-kernel the solver worked on, if it used one.
+    // Filled in by solve.
+    pub tree_decomposition: Option<TreeDecomposition>,
+}
+
+// Solution::Solution
+impl Solution {
+    // Computes the voltages and the objective breakdown of the configuration.
+    // tap_positions must contain all primary substations of the instance.
+    pub fn new(input: &SwitchSelectionInstance, tap_positions: BTreeMap<usize, TapValue>, cuts: Vec<LineCut>, statistics: SolverStatistics) -> Solution {
+        let mut voltages_sq: BTreeMap<usize, f64> = tap_positions.iter().map(|(&id, &tap_position)| (id, BASE_VOLTAGE_SQ[(tap_position + 10) as usize])).collect();
+        for cut in cuts.iter() {
+            let line_voltages_sq = input.line_voltages_sq(&cut.line, cut.cut_i, tap_positions[&cut.line[0]], tap_positions[cut.line.last().unwrap()]);
+            let last_i = cut.line.len() - 1;
+            voltages_sq.extend(cut.line[1..last_i].iter().copied().zip(line_voltages_sq[1..last_i].iter().copied()));
+        }
+        let value = tap_positions.values().map(|x| x.abs()).max().unwrap_or(0);
+        let objective = ObjectiveBreakdown {
+            value,
+            critical_primary_substations: tap_positions.iter().filter(|(_, x)| x.abs() == value).map(|(&id, _)| id).collect(),
+            min_voltage_sq: voltages_sq.iter().map(|(&id, &x)| (id, x)).min_by(|a, b| a.1.total_cmp(&b.1)).unwrap(),
+            max_voltage_sq: voltages_sq.iter().map(|(&id, &x)| (id, x)).max_by(|a, b| a.1.total_cmp(&b.1)).unwrap(),
+        };
+        Solution { tap_positions, cuts, voltages_sq, objective, optimal: true, statistics, timing: None, tree_decomposition: None }
+    }
+
+    // Reads the configuration stored in grid, which must be the grid of input
+    // with tap positions and opened switches. Each line must have exactly one
+    // opened switch.
+    pub fn from_grid(input: &SwitchSelectionInstance, grid: &SwitchSelectionGraph, statistics: SolverStatistics) -> Result<Solution, GraphError> {
+        let tap_positions = grid.iter_v().filter_map(|x| grid.v_attrs(&x).unwrap().tap_position.map(|t| (x, t))).collect();
+        let mut cuts = Vec::new();
+        for line in input.lines() {
+            let opened = (0..(line.len() - 1)).filter(|&i| grid.e_attrs(&line[i], &line[i + 1], &0).unwrap().switch).collect_vec();
+            if opened.len() != 1 {
+                return Err(GraphError::InvalidConfiguration {
+                    description: format!("The line between substations {} and {} has {} opened switches instead of 1.", line[0], line.last().unwrap(), opened.len()),
+                    vertex_ids: line,
+                });
+            }
+            cuts.push(LineCut { line, cut_i: opened[0] });
+        }
+        Ok(Solution::new(input, tap_positions, cuts, statistics))
+    }
+
+    pub fn opened_edges(&self) -> Vec<(usize, usize)> {
+        self.cuts.iter().map(|x| x.opened_edge()).collect()
+    }
+
+    // Writes the tap positions and the switches of the configuration into the
+    // grid, which must be the grid of the instance the solution was found for
+    pub fn render(&self, grid: &mut SwitchSelectionGraph) {
+        for (primary_substation_id, &tap_position) in self.tap_positions.iter() {
+            grid.v_attrs_mut(primary_substation_id).unwrap().tap_position = Some(tap_position);
+        }
+        for cut in self.cuts.iter() {
+            for i in 0..(cut.line.len() - 1) {
+                grid.e_attrs_mut(&cut.line[i], &cut.line[i + 1], &0).unwrap().switch = i == cut.cut_i;
+            }
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crabnets::ImmutableGraphContainer;
+    use crate::{solver::{base_solver::{BaseSolver, SolverStatistics}, benchmark::{generate_distribution_grid, small_grid_parameters, GeneratorParameters, Topology}, brute_force_solver::BruteForceSolver}, switch_selection_instance::SwitchSelectionInstance};
+    use super::Solution;
+
+    #[test]
+    fn rendered_solutions_are_read_back() {
+        for seed in 0..10 {
+            let parameters = GeneratorParameters { topology: Topology::RuralChain, ..small_grid_parameters(seed) };
+            let instance = SwitchSelectionInstance::new(generate_distribution_grid(2, 3, &parameters, seed)).unwrap();
+            let mut solver = BruteForceSolver::with_input(instance.clone()).unwrap();
+            if solver.solve().is_err() {
+                continue;
+            }
+            let solution = solver.get_solution().unwrap();
+            let mut grid = instance.unwrap().clone();
+            solution.render(&mut grid);
+            let read_back = Solution::from_grid(&instance, &grid, SolverStatistics::default()).unwrap();
+            assert_eq!(read_back.tap_positions, solution.tap_positions, "seed = {}", seed);
+            assert_eq!(read_back.cuts, solution.cuts, "seed = {}", seed);
+            assert_eq!(read_back.opened_edges().len(), instance.lines().len(), "seed = {}", seed);
+            assert!(read_back.voltages_sq.values().all(|x| (0.81..=1.21).contains(x)), "seed = {}", seed);
+        }
+    }
+}
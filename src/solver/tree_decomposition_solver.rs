@@ -1,42 +1,295 @@
-use std::{collections::{HashMap, HashSet, VecDeque}, sync::{mpsc::{self, Receiver, Sender}, Arc, Mutex}, thread::{self, JoinHandle}};
-use crabnets::{BasicImmutableGraph, BasicMutableGraph, ImmutableGraphContainer};
+use std::{collections::{HashMap, VecDeque}, mem, ops::Range, sync::{Arc, Condvar, Mutex}, thread};
+use crabnets::{BasicImmutableGraph, ImmutableGraphContainer};
 use itertools::Itertools;
-use crate::{switch_selection_instance::{SwitchSelectionInstance, SwitchSelectionGraph}, tree_decomposition::TreeDecomposition};
-use super::{base_solver::*, errors::SolverError};
+use crate::{switch_selection_instance::SwitchSelectionInstance, tree_decomposition::{BagKind, TreeDecomposition, TreeDecompositionParameters}};
+use super::{base_solver::*, errors::SolverError, solution::{LineCut, Solution}};
 
 
 
 
 
+// Number of possible tap positions: T = {-10, ..., 10}
+const TAP_COUNT: usize = 21;
+// Value of the combinations of tap positions that can't be extended  to  a
+// feasible solution. It exceeds any value of the objective function, so the
+// maximum of a feasible and an infeasible value is infeasible.
+const INFEASIBLE: TapValue = TapValue::MAX;
+// Smallest number of entries of a table processed by a  separate  thread.
+// Smaller tables are processed by the thread that owns the bag.
+const MIN_CHUNK_LEN: usize = 1 << 15;
+
+
+
+// Calls f(index, projected_index) for each index of a table over digit_count
+// tap positions, where projected_index is the index of the same combination
+// restricted to the tap positions with the given indices (in  that  order).
+// The mixed-radix counter avoids divisions in the inner loop.
+#[inline]
+fn for_each_projection<F: FnMut(usize, usize)>(digit_count: usize, positions: &[usize], f: F) {
+    for_each_projection_in(digit_count, positions, 0..TAP_COUNT.pow(digit_count as u32), f);
+}
+
+// Same as for_each_projection, but only for the indices in range
+fn for_each_projection_in<F: FnMut(usize, usize)>(digit_count: usize, positions: &[usize], range: Range<usize>, mut f: F) {
+    let mut projected_strides = vec![0usize; digit_count];
+    for (i, &position) in positions.iter().enumerate() {
+        projected_strides[position] = TAP_COUNT.pow((positions.len() - 1 - i) as u32);
+    }
+    // Set the counter to the beginning of the range
+    let mut digits = vec![0usize; digit_count];
+    let mut projected_index: usize = 0;
+    let mut rest = range.start;
+    for digit_i in (0..digit_count).rev() {
+        digits[digit_i] = rest % TAP_COUNT;
+        rest /= TAP_COUNT;
+        projected_index += digits[digit_i] * projected_strides[digit_i];
+    }
+    for index in range {
+        f(index, projected_index);
+        for digit_i in (0..digit_count).rev() {
+            digits[digit_i] += 1;
+            projected_index += projected_strides[digit_i];
+            if digits[digit_i] < TAP_COUNT {
+                break;
+            }
+            digits[digit_i] = 0;
+            projected_index -= TAP_COUNT * projected_strides[digit_i];
+        }
+    }
+}
+
+// Length of the contiguous chunks a table of len entries is split into so that
+// up to parallelism threads process it. The table is a single chunk  if  it's
+// too small to be worth splitting.
+#[inline]
+fn chunk_len(len: usize, parallelism: usize) -> usize {
+    len.div_ceil(parallelism.max(1)).max(MIN_CHUNK_LEN)
+}
+
+// Calls f(index, projected_index, &mut table[index]) for each entry  of  the
+// table over digit_count tap positions as for_each_projection does. Chunks of
+// the table are processed by separate threads.
+fn update_projection<F: Fn(usize, usize, &mut TapValue) + Sync>(table: &mut [TapValue], digit_count: usize, positions: &[usize], parallelism: usize, f: F) {
+    let chunk_len = chunk_len(table.len(), parallelism);
+    if chunk_len >= table.len() {
+        for_each_projection(digit_count, positions, |index, projected_index| f(index, projected_index, &mut table[index]));
+        return;
+    }
+    thread::scope(|scope| {
+        for (chunk_i, chunk) in table.chunks_mut(chunk_len).enumerate() {
+            let f = &f;
+            scope.spawn(move || {
+                let offset = chunk_i * chunk_len;
+                for_each_projection_in(digit_count, positions, offset..(offset + chunk.len()), |index, projected_index| {
+                    f(index, projected_index, &mut chunk[index - offset]);
+                });
+            });
+        }
+    });
+}
+
+// Finds the minimum of the entries of the table over digit_count tap positions
+// for each projected index as for_each_projection defines it, together  with
+// the index of the first entry that attains it. Chunks of the table are processed
+// by separate threads and their minima are merged.
+fn min_projection(table: &[TapValue], digit_count: usize, positions: &[usize], parallelism: usize) -> (Vec<TapValue>, Vec<usize>) {
+    let projected_len = TAP_COUNT.pow(positions.len() as u32);
+    let chunk_min = |offset: usize, chunk: &[TapValue]| {
+        let mut answer = (vec![INFEASIBLE; projected_len], vec![offset; projected_len]);
+        for_each_projection_in(digit_count, positions, offset..(offset + chunk.len()), |index, projected_index| {
+            if chunk[index - offset] < answer.0[projected_index] {
+                answer.0[projected_index] = chunk[index - offset];
+                answer.1[projected_index] = index;
+            }
+        });
+        answer
+    };
+    let chunk_len = chunk_len(table.len(), parallelism);
+    if chunk_len >= table.len() {
+        return chunk_min(0, table);
+    }
+    thread::scope(|scope| {
+        let handles = table.chunks(chunk_len).enumerate().map(|(chunk_i, chunk)| {
+            let chunk_min = &chunk_min;
+            scope.spawn(move || chunk_min(chunk_i * chunk_len, chunk))
+        }).collect_vec();
+        // Chunks are merged in order, so ties are broken by the lowest index
+        handles.into_iter().map(|x| x.join().unwrap()).reduce(|mut answer, other| {
+            for projected_index in 0..projected_len {
+                if other.0[projected_index] < answer.0[projected_index] {
+                    answer.0[projected_index] = other.0[projected_index];
+                    answer.1[projected_index] = other.1[projected_index];
+                }
+            }
+            answer
+        }).unwrap()
+    })
+}
+
+
+
+// Links the entries of the memo of a bag to the entries of the memo of  one
+// of its children they were derived from. The tap positions at the positions
+// of the bag form a projected index; the index of the child entry is  stored
+// in child_indices at the projected index, or is the projected index itself
+// if child_indices is None.
+#[derive(Clone)]
+struct Backpointers {
+    positions: Vec<usize>,
+    child_indices: Option<Vec<usize>>,
+}
+
+// Backpointers::Backpointers
+impl Backpointers {
+    fn child_index(&self, memo: &TapsMemo, index: usize) -> usize {
+        let digits = memo.digits(index);
+        let projected_index = self.positions.iter().fold(0, |answer, &x| answer * TAP_COUNT + digits[x]);
+        match &self.child_indices {
+            Some(child_indices) => child_indices[projected_index],
+            None => projected_index,
+        }
+    }
+}
+
+
+
+// Feasible pairs of tap positions at the endpoints of the lines between  two
+// primary substations: [l + 10][r + 10] is true if all lines can be cut so
+// that the voltages are within the bounds for tap position l at the primary
+// substation with the smaller ID and r at the other one.
+type TapPairMatrix = [[bool; TAP_COUNT]; TAP_COUNT];
+type LineTapPairs = HashMap<(usize, usize), TapPairMatrix>;
+
+// Feasible cuts of a single line: [l + 10][r + 10] is the index of a cut  of
+// the line for which its voltages are within the bounds for tap position l  at
+// line[0] and r at the other endpoint, if there's any
+type CutWitnesses = [[Option<usize>; TAP_COUNT]; TAP_COUNT];
+
+
+
+// Objective values of all combinations of tap positions at  the  primary
+// substations of a bag. The combination (t_0, ..., t_{k-1}) is  stored  at
+// index sum((t_i + 10) * 21^(k - 1 - i)), i.e. the  table  enumerates  the
+// combinations in lexicographic order.
+//
+// The entry of a combination is the lowest max |tap position| over the primary
+// substations of the subtree of the bag among all assignments of tap positions
+// to them that extend the combination and satisfy every line checked  in  the
+// subtree, or INFEASIBLE if there's no such assignment. Each line is checked in
+// some bag that contains both of its endpoints.
 #[derive(Clone)]
 struct TapsMemo {
     pub primary_substations: Vec<usize>,
-    table: HashMap<Vec<TapValue>, TapValue>,
+    table: Vec<TapValue>,
 }
 
 // TapsMemo::TapsMemo
 impl TapsMemo {
+    pub const BYTES_PER_ENTRY: usize = mem::size_of::<TapValue>();
+
+    // The entry of each combination is its max |tap position|. An empty bag has
+    // a single entry, the empty combination, whose value is 0.
     pub fn complete(primary_substations: Vec<usize>) -> TapsMemo {
-        TapsMemo {
-            primary_substations: primary_substations.clone(),
-            table: HashMap::from_iter(
-                primary_substations
-                .iter()
-                .map(|_| (-10..=10i8))
-                .multi_cartesian_product()
-                .map(|x|
-                    (x.clone(), x.iter().map(|y| y.abs()).max().unwrap())
-                )
-            )
+        let mut answer = TapsMemo::empty(primary_substations);
+        for index in 0..answer.table.len() {
+            let value = answer.digits(index).into_iter().map(|x| (x as TapValue - 10).abs()).max().unwrap_or(0);
+            answer.table[index] = value;
         }
+        answer
     }
 
     #[inline]
     pub fn empty(primary_substations: Vec<usize>) -> TapsMemo {
-        TapsMemo { primary_substations: primary_substations.clone(), table: HashMap::with_capacity(21usize.pow(primary_substations.len() as u32)) }
+        TapsMemo { primary_substations: primary_substations.clone(), table: vec![INFEASIBLE; TAP_COUNT.pow(primary_substations.len() as u32)] }
+    }
+
+    // Number of feasible combinations of tap positions
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.table.iter().filter(|&&x| x != INFEASIBLE).count()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.table.iter().all(|&x| x == INFEASIBLE)
+    }
+
+    pub fn taps_positions(&self, index: usize) -> Vec<TapValue> {
+        self.digits(index).into_iter().map(|x| x as TapValue - 10).collect()
+    }
+
+    // Tap positions of the combination stored at index shifted by 10
+    pub fn digits(&self, mut index: usize) -> Vec<usize> {
+        let mut answer = vec![0; self.primary_substations.len()];
+        for value in answer.iter_mut().rev() {
+            *value = index % TAP_COUNT;
+            index /= TAP_COUNT;
+        }
+        answer
+    }
+
+    // Marks the entries in which the tap positions of the left_i-th and the
+    // right_i-th primary substations don't form a feasible pair as infeasible
+    pub fn restrict(&mut self, left_i: usize, right_i: usize, feasible_pairs: &TapPairMatrix, parallelism: usize) {
+        let positions = if left_i == right_i { vec![left_i] } else { vec![left_i, right_i] };
+        let single_position = positions.len() == 1;
+        update_projection(&mut self.table, self.primary_substations.len(), &positions, parallelism, |_, projected_index, value| {
+            let (left_digit, right_digit) = if single_position {
+                (projected_index, projected_index)
+            } else {
+                (projected_index / TAP_COUNT, projected_index % TAP_COUNT)
+            };
+            if !feasible_pairs[left_digit][right_digit] {
+                *value = INFEASIBLE;
+            }
+        });
+    }
+
+    // Memo of a leaf of a nice tree decomposition: the  only  combination  of
+    // tap positions is the empty one
+    #[inline]
+    pub fn leaf() -> TapsMemo {
+        TapsMemo { primary_substations: Vec::new(), table: vec![0] }
+    }
+
+    // Adds a primary substation to the memo. The objective value of each new
+    // entry is the maximum of the old value and the new |tap position|.
+    pub fn introduce(&self, primary_substation: usize, parallelism: usize) -> TapsMemo {
+        let position = self.primary_substations.binary_search(&primary_substation).unwrap_err();
+        let mut primary_substations = self.primary_substations.clone();
+        primary_substations.insert(position, primary_substation);
+        let mut answer = TapsMemo::empty(primary_substations);
+        // The new tap position is the last digit of the projected index, the
+        // other digits form the index in self.table
+        let positions = (0..answer.primary_substations.len()).filter(|&x| x != position).chain([position]).collect_vec();
+        update_projection(&mut answer.table, answer.primary_substations.len(), &positions, parallelism, |_, projected_index, value| {
+            *value = self.table[projected_index / TAP_COUNT].max(((projected_index % TAP_COUNT) as TapValue - 10).abs());
+        });
+        answer
+    }
+
+    // Removes a primary substation from the memo keeping the best tap position
+    // for it
+    pub fn forget(&self, primary_substation: usize, parallelism: usize) -> (TapsMemo, Backpointers) {
+        let position = self.primary_substations.binary_search(&primary_substation).unwrap();
+        let positions = (0..self.primary_substations.len()).filter(|&x| x != position).collect_vec();
+        let (table, child_indices) = min_projection(&self.table, self.primary_substations.len(), &positions, parallelism);
+        (
+            TapsMemo { primary_substations: positions.iter().map(|&x| self.primary_substations[x]).collect(), table },
+            Backpointers { positions: (0..positions.len()).collect(), child_indices: Some(child_indices) },
+        )
+    }
+
+    // Combines two memos over the same primary substations
+    pub fn join(&mut self, other: &TapsMemo, parallelism: usize) {
+        update_projection(&mut self.table, self.primary_substations.len(), &[], parallelism, |index, _, value| {
+            *value = (*value).max(other.table[index]);
+        });
     }
 
-    pub fn intersect(&mut self, other: &TapsMemo) {
+    // Combines the memo with the memo of a child. Returns the entries of the
+    // child that the entries of the memo were combined with.
+    pub fn intersect(&mut self, other: &TapsMemo, parallelism: usize) -> Backpointers {
         let common_primary_substations_self_indices = self.primary_substations
             .iter()
             .enumerate()
@@ -45,360 +298,822 @@ impl TapsMemo {
             .collect_vec();
         let common_primary_substations_other_indices = common_primary_substations_self_indices
             .iter()
-            .map(|&x| other.primary_substations.binary_search(&self.primary_substations[x]))
-            .filter(|x| x.is_ok())
-            .map(|x| x.unwrap())
+            .map(|&x| other.primary_substations.binary_search(&self.primary_substations[x]).unwrap())
             .collect_vec();
-        // Decide what to keep and what to remove
-        // We only keep entries that have at least one corresponding  entry  in
-        // other.table.
-        // Among all the corresponding entries, we choose one with  the  lowest
-        // value of the objective function and then choose the maximum  between
-        // the value stored in that entry and the value  stored  in  the  entry
-        // from self.table.
-        let mut optimal_corresponding_entries = HashMap::new();
-        for (other_taps_positions, &other_obj_value) in other.table.iter() {
-            let other_taps_positions_for_common_primary_substations = common_primary_substations_other_indices
-                .iter()
-                .map(|&x| other_taps_positions[x])
-                .collect_vec();
-            match optimal_corresponding_entries.get_mut(&other_taps_positions_for_common_primary_substations) {
-                Some(value) => if other_obj_value < *value {
-                    *value = other_obj_value;
-                },
-                None => { optimal_corresponding_entries.insert(other_taps_positions_for_common_primary_substations, other_obj_value); },
-            }
+        // For each combination of tap positions at the common primary substations,
+        // find the lowest value of the objective function among the corresponding
+        // entries of other.table.
+        let (optimal_corresponding_entries, child_indices) = min_projection(&other.table, other.primary_substations.len(), &common_primary_substations_other_indices, parallelism);
+        // Keep the maximum between the value stored in the entry of self.table and
+        // the optimal corresponding value. Entries without a feasible corresponding
+        // entry become infeasible since INFEASIBLE exceeds every other value.
+        update_projection(&mut self.table, self.primary_substations.len(), &common_primary_substations_self_indices, parallelism, |_, projected_index, value| {
+            *value = (*value).max(optimal_corresponding_entries[projected_index]);
+        });
+        Backpointers { positions: common_primary_substations_self_indices, child_indices: Some(child_indices) }
+    }
+}
+
+
+
+// Memory usage of the memos of a single bag
+#[derive(Clone, Copy, Debug, Default)]
+struct MemoProfile {
+    // Largest number of entries allocated for a table of the bag
+    peak_entries: usize,
+    // Number of entries read while the memo of the bag was computed
+    processed_entries: usize,
+}
+
+// MemoProfile::MemoProfile
+impl MemoProfile {
+    #[inline]
+    fn observe(&mut self, memo: &TapsMemo) {
+        self.peak_entries = self.peak_entries.max(memo.table.len());
+    }
+}
+
+
+
+// Finds the range of tap positions at a primary substation that keep all squared
+// voltages of the part of the line it feeds within [0.81,  1.21]  given  the
+// highest and the lowest squared voltage  of  that  part  computed  for  the
+// squared base voltage 1.0.
+fn feasible_tap_range(voltage_sq_peak: f64, voltage_sq_gorge: f64) -> Option<(usize, usize)> {
+    let tap_position_min = BASE_VOLTAGE_SQ.iter().position(|&x| x >= 1.81 - voltage_sq_gorge)?;
+    let tap_position_max = BASE_VOLTAGE_SQ.iter().rposition(|&x| x <= 2.21 - voltage_sq_peak)?;
+    if tap_position_min > tap_position_max {
+        return None;
+    }
+    Some((tap_position_min, tap_position_max))
+}
+
+// Computes the pairs of tap positions at the endpoints of a line for which the
+// line can be cut so that all squared voltages lie in [0.81, 1.21], together
+// with such a cut for each pair.
+//
+// Tap positions only shift the voltages of the part of the line they feed, so
+// each cut allows a rectangle of pairs: the product of the feasible ranges  of
+// the left and the right tap position. For each left tap position, the right
+// ranges of the rectangles containing it are swept in the order of their
+// starts. Sums of p and q over the segments of the line are taken from prefix
+// sums, so no table of tap positions is touched.
+fn line_feasible_tap_pairs(input: &SwitchSelectionInstance, line: &[usize]) -> CutWitnesses {
+    let last_i = line.len() - 1;
+    let r = (1..line.len()).map(|i| input.e_attrs(&line[i - 1], &line[i], &0).unwrap().r).collect_vec();
+    let x = (1..line.len()).map(|i| input.e_attrs(&line[i - 1], &line[i], &0).unwrap().x).collect_vec();
+    // p_prefix[i] is the sum of p over line[..i]
+    let mut p_prefix = vec![0.0; line.len() + 1];
+    let mut q_prefix = vec![0.0; line.len() + 1];
+    for (i, substation_id) in line.iter().enumerate() {
+        p_prefix[i + 1] = p_prefix[i] + input.v_attrs(substation_id).unwrap().p;
+        q_prefix[i + 1] = q_prefix[i] + input.v_attrs(substation_id).unwrap().q;
+    }
+    // (cut_i, left range, right range) of each feasible cut
+    let mut rectangles = Vec::new();
+    // Try opening the switch on each edge (line[cut_i], line[cut_i + 1])
+    for cut_i in 0..last_i {
+        // Left part: line[0..=cut_i] is fed from line[0]
+        let mut voltage_sq: f64 = 1.0;
+        let mut voltage_sq_peak: f64 = 1.0;
+        let mut voltage_sq_gorge: f64 = 1.0;
+        for substation_i in 1..=cut_i {
+            voltage_sq += x[substation_i - 1] * (q_prefix[cut_i + 1] - q_prefix[substation_i])
+                        - r[substation_i - 1] * (p_prefix[cut_i + 1] - p_prefix[substation_i]);
+            voltage_sq_peak = voltage_sq_peak.max(voltage_sq);
+            voltage_sq_gorge = voltage_sq_gorge.min(voltage_sq);
+        }
+        let left_range = match feasible_tap_range(voltage_sq_peak, voltage_sq_gorge) {
+            Some(value) => value,
+            None => continue,
+        };
+        // Right part: line[(cut_i + 1)..] is fed from line[last_i]
+        voltage_sq = 1.0;
+        voltage_sq_peak = 1.0;
+        voltage_sq_gorge = 1.0;
+        for substation_i in ((cut_i + 1)..last_i).rev() {
+            voltage_sq += x[substation_i] * (q_prefix[substation_i + 1] - q_prefix[cut_i + 1])
+                        - r[substation_i] * (p_prefix[substation_i + 1] - p_prefix[cut_i + 1]);
+            voltage_sq_peak = voltage_sq_peak.max(voltage_sq);
+            voltage_sq_gorge = voltage_sq_gorge.min(voltage_sq);
         }
-        let mut entries_to_be_removed = Vec::new();
-        for (taps_positions, obj_value) in self.table.iter_mut() {
-            let taps_positions_for_common_primary_substations = common_primary_substations_self_indices
-                .iter()
-                .map(|&x| taps_positions[x])
-                .collect_vec();
-            match optimal_corresponding_entries.get(&taps_positions_for_common_primary_substations) {
-                Some(&value) => if *obj_value < value {
-                    *obj_value = value;
-                },
-                None => entries_to_be_removed.push(taps_positions.clone()),
+        let right_range = match feasible_tap_range(voltage_sq_peak, voltage_sq_gorge) {
+            Some(value) => value,
+            None => continue,
+        };
+        rectangles.push((cut_i, left_range, right_range));
+    }
+    let mut answer = [[None; TAP_COUNT]; TAP_COUNT];
+    for (left_i, witnesses) in answer.iter_mut().enumerate() {
+        // reaches[start] is the end of the right range starting at start that
+        // reaches the farthest among the rectangles containing left_i, with its cut
+        let mut reaches: [Option<(usize, usize)>; TAP_COUNT] = [None; TAP_COUNT];
+        for &(cut_i, left_range, right_range) in rectangles.iter() {
+            if (left_range.0..=left_range.1).contains(&left_i) {
+                reaches[right_range.0] = reaches[right_range.0].max(Some((right_range.1, cut_i)));
             }
         }
-        for taps_position in entries_to_be_removed.drain(..) {
-            self.table.remove(&taps_position);
+        let mut farthest: Option<(usize, usize)> = None;
+        for (right_i, (witness, reach)) in witnesses.iter_mut().zip(reaches).enumerate() {
+            farthest = farthest.max(reach);
+            *witness = farthest.filter(|&(end, _)| end >= right_i).map(|(_, cut_i)| cut_i);
         }
     }
+    answer
 }
 
+// Feasible pairs of tap positions for every pair of  primary  substations
+// connected by lines, and the feasible cuts of each line in the order  of
+// SwitchSelectionInstance::lines. All lines between the same pair must  be
+// cut, so their matrices are intersected.
+fn all_lines_feasible_tap_pairs(input: &SwitchSelectionInstance) -> (LineTapPairs, Vec<(Vec<usize>, CutWitnesses)>) {
+    let mut answer: LineTapPairs = HashMap::new();
+    let mut line_cuts = Vec::new();
+    for line in input.lines() {
+        let witnesses = line_feasible_tap_pairs(input, &line);
+        let endpoints = (line[0], *line.last().unwrap());
+        let value = answer.entry(endpoints).or_insert([[true; TAP_COUNT]; TAP_COUNT]);
+        for (row, witness_row) in value.iter_mut().zip(witnesses.iter()) {
+            for (feasible, witness) in row.iter_mut().zip(witness_row.iter()) {
+                *feasible &= witness.is_some();
+            }
+        }
+        line_cuts.push((line, witnesses));
+    }
+    (answer, line_cuts)
+}
 
-
-fn locally_feasible_taps_positions(input: Arc<SwitchSelectionInstance>, bag: &Vec<usize>) -> TapsMemo {
-    // Possible values of the square voltage at a primary substation.
-    // Tap positions are: T = {-10, ..., 10}.
-    // Base voltage: B = {1 + 0.1 * t | t \in T}.
-    // Square base voltage: {u² | u \in B}; for each t \in T the  squared  base
-    // voltage corresponding to t is BASE_VOLTAGE_SQ[t + 10].
-    const BASE_VOLTAGE_SQ: [f64; 21] = [0.81, 0.8281, 0.8464, 0.8649, 0.8836, 0.9025, 0.9216, 0.9409, 0.9604, 0.9801, 1.0,
-                                        1.0201, 1.0404, 1.0609, 1.0816, 1.1025, 1.1236, 1.1449, 1.1664, 1.1881, 1.21];
+fn locally_feasible_taps_positions(line_tap_pairs: &LineTapPairs, bag: &Vec<usize>, parallelism: usize, profile: &mut MemoProfile) -> TapsMemo {
     let mut answer = TapsMemo::complete(bag.clone());
+    profile.observe(&answer);
     // Consider all possible pairs of primary  substations  from  the  bag.  If
-    // there're lines between a pair of the primary  substations,  try  cutting
-    // each line in different places and see which tap positions are feasible.
+    // there're lines between a pair of the primary  substations,  remove  the
+    // tap positions that don't form a feasible pair for all of them.
     for left_primary_substation_i in 0..bag.len() {
-        let left_primary_substation_id = bag[left_primary_substation_i];
         for right_primary_substation_i in left_primary_substation_i..bag.len() {
-            let right_primary_substation_id = bag[right_primary_substation_i];
-            for adjacent_id in input.iter_adjacent(&left_primary_substation_id).unwrap() {
-                // If the adjacent vertex is  a  secondary  substation  lying  on  a  line  between
-                // left_primary_substation_id  and  right_primary_substation_id,  reconstruct   the
-                // entire line.
-                match input.v_attrs(&adjacent_id).unwrap().line_endpoints {
-                    Some(value) => if value != (left_primary_substation_id, right_primary_substation_id) {
-                        continue;
-                    },
-                    None => continue,
-                }
-                let mut line = Vec::from([left_primary_substation_id, adjacent_id]);
-                loop {
-                    let last_discovered_substation_id = line.last().unwrap();
-                    if input.v_attrs(last_discovered_substation_id).unwrap().tap_position.is_some() {
-                        break;
-                    }
-                    for adjacent_to_last_id in input.iter_adjacent(last_discovered_substation_id).unwrap() {
-                        if adjacent_to_last_id != line[line.len() - 2] {
-                            line.push(adjacent_to_last_id);
-                            break;
-                        }
-                    }
-                }
-                // Now that we have a full line, cut all possible edges on  it  one  after  another
-                // and compute feasible tap positions for every cut.
-                let mut line_memo = TapsMemo::empty(bag.clone());
-                for (last_left_substation_i, first_right_substation_i) in (0..(line.len() - 1)).zip(1..line.len()) {
-                    let left_line = &line[..=last_left_substation_i];
-                    let right_line = &line[first_right_substation_i..];
-                    let mut voltage_sq = 1.0;
-                    let mut voltage_sq_peak: f64 = 1.0;
-                    let mut voltage_sq_gorge: f64 = 1.0;
-                    for left_substation_i in 1..left_line.len() {
-                        voltage_sq += input.e_attrs(&left_line[left_substation_i - 1], &left_line[left_substation_i], &0).unwrap().x
-                                    * left_line[left_substation_i..].iter().map(|x| input.v_attrs(x).unwrap().q).sum::<f64>()
-                                    - input.e_attrs(&left_line[left_substation_i - 1], &left_line[left_substation_i], &0).unwrap().r
-                                    * left_line[left_substation_i..].iter().map(|x| input.v_attrs(x).unwrap().p).sum::<f64>();
-                        voltage_sq_peak = voltage_sq_peak.max(voltage_sq);
-                        voltage_sq_gorge = voltage_sq_gorge.min(voltage_sq);
-                    }
-                    if voltage_sq_peak - voltage_sq_gorge > 1.21 - 0.81 {
-                        continue;
-                    }
-                    let left_tap_position_min = BASE_VOLTAGE_SQ.iter().enumerate().filter(|&(_, &x)| x >= 1.81 - voltage_sq_gorge).next().unwrap().0 as TapValue - 10;
-                    let left_tap_position_max = BASE_VOLTAGE_SQ.iter().enumerate().rev().filter(|&(_, &x)| x <= 2.21 - voltage_sq_peak).next().unwrap().0 as TapValue - 10;
-                    voltage_sq = 1.0;
-                    voltage_sq_peak = 1.0;
-                    voltage_sq_gorge = 1.0;
-                    for right_substation_i in (0..(right_line.len() - 1)).rev() {
-                        voltage_sq += input.e_attrs(&right_line[right_substation_i + 1], &right_line[right_substation_i], &0).unwrap().x
-                                    * right_line[..=right_substation_i].iter().map(|x| input.v_attrs(x).unwrap().q).sum::<f64>()
-                                    - input.e_attrs(&right_line[right_substation_i + 1], &right_line[right_substation_i], &0).unwrap().r
-                                    * right_line[..=right_substation_i].iter().map(|x| input.v_attrs(x).unwrap().p).sum::<f64>();
-                        voltage_sq_peak = voltage_sq_peak.max(voltage_sq);
-                        voltage_sq_gorge = voltage_sq_gorge.min(voltage_sq);
-                    }
-                    if voltage_sq_peak - voltage_sq_gorge > 1.21 - 0.81 {
-                        continue;
-                    }
-                    let right_tap_position_min = BASE_VOLTAGE_SQ.iter().enumerate().filter(|&(_, &x)| x >= 1.81 - voltage_sq_gorge).next().unwrap().0 as TapValue - 10;
-                    let right_tap_position_max = BASE_VOLTAGE_SQ.iter().enumerate().rev().filter(|&(_, &x)| x <= 2.21 - voltage_sq_peak).next().unwrap().0 as TapValue - 10;
-                    // The following operation can take almost 90% of all computation  time!!!  Can
-                    // be optimised by, e.g. replacing these memos with DataFrames and using  joins
-                    // instead of extending every line_memo with dozens of rows.
-                    line_memo.table.extend(answer.table.iter().filter(|&(x, _)|
-                        x[left_primary_substation_i] >= left_tap_position_min &&
-                        x[left_primary_substation_i] <= left_tap_position_max &&
-                        x[right_primary_substation_i] >= right_tap_position_min &&
-                        x[right_primary_substation_i] <= right_tap_position_max
-                    ).map(|(k, v)| (k.clone(), v.clone())));
-                }
-                answer = line_memo;
+            if let Some(feasible_pairs) = line_tap_pairs.get(&(bag[left_primary_substation_i], bag[right_primary_substation_i])) {
+                profile.processed_entries += answer.table.len();
+                answer.restrict(left_primary_substation_i, right_primary_substation_i, feasible_pairs, parallelism);
             }
         }
     }
     answer
 }
 
-fn thread_workload(input: Arc<SwitchSelectionInstance>, memos: Arc<Mutex<HashMap<usize, TapsMemo>>>, td: Arc<TreeDecomposition>, bag_id: usize, rx: Receiver<usize>) -> Result<(), SolverError> {
-    // Create a memo for this bag
-    let bag = td.v_attrs(&bag_id).unwrap().vertices.clone();
-    let mut memo = locally_feasible_taps_positions(input, &bag);
-    // Intersect memo with the memos of the children
-    let mut remaining_children: HashSet<usize> = td.iter_adjacent_out(&bag_id).unwrap().collect();
-    while !remaining_children.is_empty() {
-        let received_bag_id = match rx.recv() {
-            Ok(value) => value,
-            Err(_) => return Ok(()),
-        };
-        if remaining_children.contains(&received_bag_id) {
-            let child_memo = memos.lock().unwrap()[&received_bag_id].clone();
-            memo.intersect(&child_memo);
-            remaining_children.remove(&received_bag_id);
-        }
-    }
-    // If memo is empty, the instance is infeasible
-    if memo.table.is_empty() {
-        return Err(SolverError::from_str("TreeDecompositionSolver. The problem instance is infeasible."));
-    }
-    // Otherwise, save in the memos collection and send it to the parent
-    memos.lock().unwrap().insert(bag_id, memo);
-    Ok(())
-}
-
-fn solution_graph_setup(solution: &mut SwitchSelectionGraph, dg_kernel: &SwitchSelectionGraph, taps_positions: &HashMap<usize, TapValue>) {
-    // Possible values of the square voltage at a primary substation.
-    // Tap positions are: T = {-10, ..., 10}.
-    // Base voltage: B = {1 + 0.1 * t | t \in T}.
-    // Square base voltage: {u² | u \in B}; for each t \in T the  squared  base
-    // voltage corresponding to t is BASE_VOLTAGE_SQ[t + 10].
-    const BASE_VOLTAGE_SQ: [f64; 21] = [0.81, 0.8281, 0.8464, 0.8649, 0.8836, 0.9025, 0.9216, 0.9409, 0.9604, 0.9801, 1.0,
-                                        1.0201, 1.0404, 1.0609, 1.0816, 1.1025, 1.1236, 1.1449, 1.1664, 1.1881, 1.21];
-    for (left_primary_substation_id, right_primary_substation_id) in dg_kernel.iter_e().map(|x| (x.id1, x.id2)) {
-        for adjacent_id in solution.iter_adjacent(&left_primary_substation_id).unwrap().collect_vec() {
-            // If the adjacent vertex is  a  secondary  substation  lying  on  a  line  between
-            // left_primary_substation_id  and  right_primary_substation_id,  reconstruct   the
-            // entire line.
-            match solution.v_attrs(&adjacent_id).unwrap().line_endpoints {
-                Some(value) => if value != (left_primary_substation_id, right_primary_substation_id) {
-                    continue;
-                },
-                None => continue,
-            }
-            let mut line = Vec::from([left_primary_substation_id, adjacent_id]);
-            loop {
-                let last_discovered_substation_id = line.last().unwrap();
-                if solution.v_attrs(last_discovered_substation_id).unwrap().tap_position.is_some() {
-                    break;
-                }
-                for adjacent_to_last_id in solution.iter_adjacent(last_discovered_substation_id).unwrap() {
-                    if adjacent_to_last_id != line[line.len() - 2] {
-                        line.push(adjacent_to_last_id);
-                        break;
-                    }
+// Computes the memo of a bag of a nice tree decomposition from the memos of
+// its children. Each line is checked at the bag that introduces the second of
+// its endpoints.
+fn nice_bag_memo(line_tap_pairs: &LineTapPairs, kind: BagKind, child_memos: &[Arc<TapsMemo>], parallelism: usize, profile: &mut MemoProfile) -> (TapsMemo, Vec<Backpointers>) {
+    let (answer, backpointers) = match kind {
+        BagKind::Leaf => (TapsMemo::leaf(), Vec::new()),
+        BagKind::Introduce(primary_substation_id) => {
+            let mut answer = child_memos[0].introduce(primary_substation_id, parallelism);
+            let primary_substation_i = answer.primary_substations.binary_search(&primary_substation_id).unwrap();
+            for other_primary_substation_i in 0..answer.primary_substations.len() {
+                let (left_i, right_i) = if other_primary_substation_i <= primary_substation_i {
+                    (other_primary_substation_i, primary_substation_i)
+                } else {
+                    (primary_substation_i, other_primary_substation_i)
+                };
+                if let Some(feasible_pairs) = line_tap_pairs.get(&(answer.primary_substations[left_i], answer.primary_substations[right_i])) {
+                    answer.restrict(left_i, right_i, feasible_pairs, parallelism);
                 }
             }
-            // Now that we have a full line, cut all possible edges on  it  one  after  another
-            // until a feasible cut is found.
-            for (last_left_substation_i, first_right_substation_i) in (0..(line.len() - 1)).zip(1..line.len()) {
-                let left_line = &line[..=last_left_substation_i];
-                let right_line = &line[first_right_substation_i..];
-                let mut voltage_sq = BASE_VOLTAGE_SQ[(taps_positions[&left_primary_substation_id] + 10) as usize];
-                let mut voltage_sq_peak = voltage_sq;
-                let mut voltage_sq_gorge = voltage_sq;
-                for left_substation_i in 1..left_line.len() {
-                    voltage_sq += solution.e_attrs(&left_line[left_substation_i - 1], &left_line[left_substation_i], &0).unwrap().x
-                                * left_line[left_substation_i..].iter().map(|x| solution.v_attrs(x).unwrap().q).sum::<f64>()
-                                - solution.e_attrs(&left_line[left_substation_i - 1], &left_line[left_substation_i], &0).unwrap().r
-                                * left_line[left_substation_i..].iter().map(|x| solution.v_attrs(x).unwrap().p).sum::<f64>();
-                    voltage_sq_peak = voltage_sq_peak.max(voltage_sq);
-                    voltage_sq_gorge = voltage_sq_gorge.min(voltage_sq);
-                }
-                if voltage_sq_peak > 1.21 || voltage_sq_gorge < 0.81 {
-                    continue;
-                }
-                voltage_sq = BASE_VOLTAGE_SQ[(taps_positions[&right_primary_substation_id] + 10) as usize];
-                voltage_sq_peak = voltage_sq;
-                voltage_sq_gorge = voltage_sq;
-                for right_substation_i in (0..(right_line.len() - 1)).rev() {
-                    voltage_sq += solution.e_attrs(&right_line[right_substation_i + 1], &right_line[right_substation_i], &0).unwrap().x
-                                * right_line[..=right_substation_i].iter().map(|x| solution.v_attrs(x).unwrap().q).sum::<f64>()
-                                - solution.e_attrs(&right_line[right_substation_i + 1], &right_line[right_substation_i], &0).unwrap().r
-                                * right_line[..=right_substation_i].iter().map(|x| solution.v_attrs(x).unwrap().p).sum::<f64>();
-                    voltage_sq_peak = voltage_sq_peak.max(voltage_sq);
-                    voltage_sq_gorge = voltage_sq_gorge.min(voltage_sq);
-                }
-                if voltage_sq_peak > 1.21 || voltage_sq_gorge < 0.81 {
-                    continue;
-                }
-                // If we reach this point, we've found a feasible cut. Record it in the graph.
-                solution.e_attrs_mut(&line[last_left_substation_i], &line[first_right_substation_i], &0).unwrap().switch = true;
-                break;
+            // The other tap positions form the index of the child entry
+            let positions = (0..answer.primary_substations.len()).filter(|&x| x != primary_substation_i).collect_vec();
+            (answer, vec![Backpointers { positions, child_indices: None }])
+        },
+        BagKind::Forget(primary_substation_id) => {
+            let (answer, backpointers) = child_memos[0].forget(primary_substation_id, parallelism);
+            (answer, vec![backpointers])
+        },
+        BagKind::Join => {
+            let mut answer = child_memos[0].as_ref().clone();
+            answer.join(&child_memos[1], parallelism);
+            // Both children have the same primary substations as the bag
+            let backpointers = Backpointers { positions: (0..answer.primary_substations.len()).collect(), child_indices: None };
+            (answer, vec![backpointers.clone(), backpointers])
+        },
+    };
+    profile.processed_entries += child_memos.iter().map(|x| x.table.len()).sum::<usize>();
+    profile.observe(&answer);
+    (answer, backpointers)
+}
+
+// Computes the memo of a bag from the memos of its children and the backpointers
+// to each of them. Up to parallelism threads process each table of the bag.
+fn bag_memo(line_tap_pairs: &LineTapPairs, td: &TreeDecomposition, bag_id: usize, child_memos: &[Arc<TapsMemo>], parallelism: usize) -> Result<BagResult, SolverError> {
+    let bag = td.v_attrs(&bag_id).unwrap();
+    let mut profile = MemoProfile::default();
+    let (memo, backpointers) = match bag.kind {
+        // In a nice tree decomposition, the memo of a bag  is  derived  from  the
+        // memos of its children
+        Some(kind) => nice_bag_memo(line_tap_pairs, kind, child_memos, parallelism, &mut profile),
+        // Otherwise, create a memo for this bag and intersect it with the memos of
+        // the children
+        None => {
+            let mut memo = locally_feasible_taps_positions(line_tap_pairs, &bag.vertices, parallelism, &mut profile);
+            let mut backpointers = Vec::with_capacity(child_memos.len());
+            for child_memo in child_memos {
+                profile.processed_entries += memo.table.len() + child_memo.table.len();
+                backpointers.push(memo.intersect(child_memo, parallelism));
             }
-        }
-    }
-    for primary_substation_id in dg_kernel.iter_v() {
-        solution.v_attrs_mut(&primary_substation_id).unwrap().tap_position = Some(taps_positions[&primary_substation_id]);
+            (memo, backpointers)
+        },
+    };
+    // If memo is empty, the instance is infeasible: no tap positions of the primary
+    // substations of the bag are consistent with the lines of its subtree
+    if memo.is_empty() {
+        return Err(SolverError::Infeasible { description: "TreeDecompositionSolver. The problem instance is infeasible.".to_string(), vertex_ids: bag.vertices.clone() });
     }
+    Ok(BagResult { memo, backpointers, profile })
+}
+
+// Memo of a bag with its backpointers to the children in the ascending order
+// of their IDs
+struct BagResult {
+    memo: TapsMemo,
+    backpointers: Vec<Backpointers>,
+    profile: MemoProfile,
 }
 
 
 
-struct ThreadMetadata {
-    bag_id: usize,
-    join_handle: Option<JoinHandle<Result<(), SolverError>>>,
-    tx: Option<Sender<usize>>,
+// Progress of the dynamic programming shared by the workers. A bag is ready
+// as soon as the memos of all its children are computed.
+struct SchedulerState {
+    ready_bags: Vec<usize>,
+    remaining_children: HashMap<usize, usize>,
+    parents: HashMap<usize, usize>,
+    remaining_bag_count: usize,
+    busy_worker_count: usize,
+    memos: HashMap<usize, Arc<TapsMemo>>,
+    backpointers: HashMap<usize, Vec<Backpointers>>,
+    memo_profiles: HashMap<usize, MemoProfile>,
+    error: Option<SolverError>,
 }
 
+// SchedulerState::SchedulerState
+impl SchedulerState {
+    fn new(td: &TreeDecomposition) -> SchedulerState {
+        let remaining_children: HashMap<usize, usize> = td.iter_v().map(|x| (x, td.iter_adjacent_out(&x).unwrap().count())).collect();
+        SchedulerState {
+            ready_bags: remaining_children.iter().filter(|&(_, &x)| x == 0).map(|(&x, _)| x).sorted().rev().collect(),
+            parents: td.iter_e().map(|x| (x.id2, x.id1)).collect(),
+            remaining_bag_count: remaining_children.len(),
+            remaining_children,
+            busy_worker_count: 0,
+            memos: HashMap::new(),
+            backpointers: HashMap::new(),
+            memo_profiles: HashMap::new(),
+            error: None,
+        }
+    }
+
+    // Saves the memo of the bag and marks its parent as ready if it was the last
+    // child to be computed
+    fn complete(&mut self, bag_id: usize, result: BagResult) {
+        self.memos.insert(bag_id, Arc::new(result.memo));
+        self.backpointers.insert(bag_id, result.backpointers);
+        self.memo_profiles.insert(bag_id, result.profile);
+        self.remaining_bag_count -= 1;
+        if let Some(&parent_id) = self.parents.get(&bag_id) {
+            let remaining_children = self.remaining_children.get_mut(&parent_id).unwrap();
+            *remaining_children -= 1;
+            if *remaining_children == 0 {
+                self.ready_bags.push(parent_id);
+            }
+        }
+    }
+}
 
+struct Scheduler {
+    state: Mutex<SchedulerState>,
+    bag_ready: Condvar,
+}
+
+// Takes ready bags until all memos are computed, some bag turns out  to  be
+// infeasible or the deadline passes. The most recently readied bag is taken
+// first, so the workers climb towards the root instead of spreading over  the
+// leaves. A bag taken while other workers are idle borrows their threads  to
+// process its tables.
+fn worker(scheduler: &Scheduler, line_tap_pairs: &LineTapPairs, td: &TreeDecomposition, thread_count: usize, deadline: &Deadline) {
+    loop {
+        let (bag_id, child_memos, parallelism) = {
+            let mut state = scheduler.state.lock().unwrap();
+            let bag_id = loop {
+                if state.error.is_some() || state.remaining_bag_count == 0 {
+                    return;
+                }
+                if let Err(error) = deadline.check("TreeDecompositionSolver") {
+                    state.error = Some(error);
+                    scheduler.bag_ready.notify_all();
+                    return;
+                }
+                if let Some(value) = state.ready_bags.pop() {
+                    break value;
+                }
+                state = scheduler.bag_ready.wait(state).unwrap();
+            };
+            state.busy_worker_count += 1;
+            let child_memos = td.iter_adjacent_out(&bag_id).unwrap().sorted().map(|x| state.memos[&x].clone()).collect_vec();
+            // Idle workers that won't take the remaining ready bags
+            let idle_worker_count = (thread_count - state.busy_worker_count).saturating_sub(state.ready_bags.len());
+            (bag_id, child_memos, idle_worker_count + 1)
+        };
+        let result = bag_memo(line_tap_pairs, td, bag_id, &child_memos, parallelism);
+        let mut state = scheduler.state.lock().unwrap();
+        state.busy_worker_count -= 1;
+        match result {
+            Ok(result) => state.complete(bag_id, result),
+            Err(error) => state.error = Some(error),
+        }
+        scheduler.bag_ready.notify_all();
+    }
+}
 
 pub struct TreeDecompositionSolver {
-    dg_kernel: SwitchSelectionGraph,
     input: Arc<SwitchSelectionInstance>,
+    line_tap_pairs: Arc<LineTapPairs>,
+    // Feasible cuts of each line, used to reconstruct the solution
+    line_cuts: Vec<(Vec<usize>, CutWitnesses)>,
     td: Arc<TreeDecomposition>,
     memos: Option<HashMap<usize, TapsMemo>>,
+    // Backpointers of each bag to its children in the ascending order of their IDs
+    backpointers: HashMap<usize, Vec<Backpointers>>,
+    memo_profiles: HashMap<usize, MemoProfile>,
     thread_count: usize,
+    limits: SolverLimits,
 }
 
-// TreeDecompositionSolver::BaseSolver
-impl BaseSolver for TreeDecompositionSolver {
-    fn with_input(input: SwitchSelectionInstance) -> Result<Self, SolverError> {
+// TreeDecompositionSolver::TreeDecompositionSolver
+impl TreeDecompositionSolver {
+    // If nice is true, the tree decomposition is converted into a  nice  one  and
+    // introduce, forget and join bags are processed separately
+    pub fn new(input: SwitchSelectionInstance, nice: bool, td_parameters: &TreeDecompositionParameters) -> Result<Self, SolverError> {
         let dg_kernel = input.dg_kernel_for_switch_selection();
-        let td = match TreeDecomposition::for_switch_selection_graph(&dg_kernel) {
+        // A grid with a single primary substation has no lines, so its DG-kernel
+        // is empty. The only bag then holds the primary substation.
+        let td = if dg_kernel.count_v() == 0 {
+            let primary_substations = input.iter_v().filter(|x| input.v_attrs(x).unwrap().tap_position.is_some()).collect_vec();
+            TreeDecomposition::from_bags(vec![primary_substations], &[], td_parameters.algorithm)
+        } else {
+            TreeDecomposition::with_parameters(&dg_kernel, td_parameters)
+        };
+        let mut td = match td {
             Ok(value) => value,
-            Err(value) => return Err(SolverError::from_string(value.to_string())),
+            Err(value) => return Err(SolverError::TreeDecompositionFailed {
+                description: "TreeDecompositionSolver. Failed to compute a tree decomposition of the DG-kernel.".to_string(),
+                source: value,
+            }),
         };
+        if nice {
+            td = td.to_nice();
+        }
+        let (line_tap_pairs, line_cuts) = all_lines_feasible_tap_pairs(&input);
         Ok(TreeDecompositionSolver {
-            dg_kernel,
+            line_tap_pairs: Arc::new(line_tap_pairs),
+            line_cuts,
             input: Arc::new(input),
             td: Arc::new(td),
             memos: None,
-            thread_count: num_cpus::get() - 1,
+            backpointers: HashMap::new(),
+            memo_profiles: HashMap::new(),
+            thread_count: num_cpus::get(),
+            limits: SolverLimits::default(),
         })
     }
 
-    fn get_solution(&self) -> Option<(SwitchSelectionGraph, TapValue)> {
-        if self.memos.is_none() {
-            return None;
+    // Sets the number of workers that compute the memos. With a single worker
+    // the memos are computed sequentially, and 0 is treated as 1 since no memo
+    // would be computed otherwise.
+    pub fn set_thread_count(&mut self, thread_count: usize) {
+        self.thread_count = thread_count.max(1);
+    }
+
+    // Refuses the problem instance before its tree decomposition is computed,
+    // which may take long with the exact algorithm, if any decomposition would
+    // exceed the memory budget. Any decomposition of the DG-kernel has a bag of
+    // more than width_lower_bound primary substations, and the memo  of  that
+    // bag alone has 21^(bag size) entries. The estimate of the decomposition
+    // actually used is checked by check_memory_budget afterwards.
+    pub fn check_memory_lower_bound(input: &SwitchSelectionInstance, solver_name: &str, budget: Option<usize>) -> Result<(), SolverError> {
+        let budget = match budget {
+            Some(value) => value,
+            None => return Ok(()),
+        };
+        let bag_size = TreeDecomposition::width_lower_bound(&input.dg_kernel_for_switch_selection()) + 1;
+        let estimate = TAP_COUNT.checked_pow(bag_size as u32).unwrap_or(usize::MAX).saturating_mul(TapsMemo::BYTES_PER_ENTRY);
+        if estimate > budget {
+            return Err(memory_budget_error(solver_name, estimate, budget));
+        }
+        Ok(())
+    }
+
+    // Computes the memos of the bags one after another in depth-first search
+    // postordering on the calling thread without spawning any threads
+    fn solve_sequentially(&mut self, deadline: &Deadline) -> Result<(), SolverError> {
+        let mut memos: HashMap<usize, Arc<TapsMemo>> = HashMap::new();
+        for bag_id in self.td.dfs_postordering() {
+            deadline.check("TreeDecompositionSolver")?;
+            let child_memos = self.td.iter_adjacent_out(&bag_id).unwrap().sorted().map(|x| memos[&x].clone()).collect_vec();
+            let result = bag_memo(&self.line_tap_pairs, &self.td, bag_id, &child_memos, 1)?;
+            memos.insert(bag_id, Arc::new(result.memo));
+            self.backpointers.insert(bag_id, result.backpointers);
+            self.memo_profiles.insert(bag_id, result.profile);
         }
-        let memos = self.memos.as_ref().unwrap();
-        let mut answer = self.input.as_ref().unwrap().clone();
-        // Tap positions will be gradually collected in taps_positions
+        self.memos = Some(memos.into_iter().map(|(bag_id, memo)| (bag_id, Arc::into_inner(memo).unwrap())).collect());
+        Ok(())
+    }
+}
+
+// TreeDecompositionSolver::BaseSolver
+impl BaseSolver for TreeDecompositionSolver {
+    fn with_input(input: SwitchSelectionInstance) -> Result<Self, SolverError> {
+        TreeDecompositionSolver::new(input, false, &TreeDecompositionParameters::default())
+    }
+
+    // Follows the backpointers from the optimal entry of the root memo  down  to
+    // the leaves, so each bag gets the entry that its parent's entry was derived
+    // from, and the tap positions of all bags agree.
+    fn get_solution(&self) -> Option<Solution> {
+        let memos = self.memos.as_ref()?;
         let mut taps_positions: HashMap<usize, TapValue> = HashMap::new();
-        // Traverse the tree decomposition  top-down  in  breadth-first  search
-        // pre-ordering to construct a solution
-        let mut bag_queue: VecDeque<usize> = VecDeque::from([self.td.root_id]);
-        while !bag_queue.is_empty() {
-            // Get the ID of the current bag.
-            let curr_bag_id = bag_queue.pop_front().unwrap();
-            // Find the best locally feasible solution that  agrees  with  what
-            // was already built in taps_positions.
-            let curr_entry = memos[&curr_bag_id].table
-                .iter()
-                .sorted_by_key(|&(_, &x)| x)
-                .find(|&(k, _)|
-                    k.iter().enumerate().all(|(i, &x)|
-                        match taps_positions.get(&memos[&curr_bag_id].primary_substations[i]) {
-                            Some(&value) => x == value,
-                            None => true,
-                        }
-                    )
-                )
-                .unwrap()
-                .0
-                .iter()
-                .enumerate()
-                .map(|(i, &x)| (memos[&curr_bag_id].primary_substations[i], x));
-            taps_positions.extend(curr_entry);
-            bag_queue.extend(self.td.iter_adjacent_out(&curr_bag_id).unwrap());
-        }
-        solution_graph_setup(&mut answer, &self.dg_kernel, &taps_positions);
-        Some((answer, memos[&self.td.root_id].table.iter().sorted_by_key(|&(_, &x)| x).next().unwrap().1.clone()))
+        let root_memo = &memos[&self.td.root_id];
+        let root_index = root_memo.table.iter().position_min().unwrap();
+        let mut bag_queue: VecDeque<(usize, usize)> = VecDeque::from([(self.td.root_id, root_index)]);
+        while let Some((curr_bag_id, curr_index)) = bag_queue.pop_front() {
+            let curr_memo = &memos[&curr_bag_id];
+            taps_positions.extend(curr_memo.primary_substations.iter().copied().zip(curr_memo.taps_positions(curr_index)));
+            let children = self.td.iter_adjacent_out(&curr_bag_id).unwrap().sorted();
+            for (child_id, backpointers) in children.zip(self.backpointers[&curr_bag_id].iter()) {
+                bag_queue.push_back((child_id, backpointers.child_index(curr_memo, curr_index)));
+            }
+        }
+        // The tap positions form feasible pairs for all lines, so each line has
+        // a witness cut computed along with the pairs
+        let cuts = self.line_cuts.iter().map(|(line, witnesses)| LineCut {
+            line: line.clone(),
+            cut_i: witnesses[(taps_positions[&line[0]] + 10) as usize][(taps_positions[line.last().unwrap()] + 10) as usize].unwrap(),
+        }).collect();
+        Some(Solution::new(&self.input, taps_positions.into_iter().collect(), cuts, self.get_statistics()))
+    }
+
+    fn get_statistics(&self) -> SolverStatistics {
+        SolverStatistics {
+            tree_decomposition_width: Some(self.td.max_bag_size - 1),
+            memo_sizes: match &self.memos {
+                Some(memos) => memos.iter().map(|(&bag_id, memo)| (bag_id, memo.len())).sorted().collect(),
+                None => Vec::new(),
+            },
+            peak_memo_sizes: self.memo_profiles.iter().map(|(&bag_id, profile)| (bag_id, profile.peak_entries)).sorted().collect(),
+            processed_memo_entries: self.memo_profiles.values().map(|x| x.processed_entries).sum(),
+        }
     }
 
+    fn get_tree_decomposition(&self) -> Option<&TreeDecomposition> {
+        Some(&self.td)
+    }
+
+    // The memos and the backpointers of all bags are kept until the  solution
+    // is reconstructed. A memo has an entry for each of the 21^(bag size)
+    // combinations of tap positions, and the backpointers of a bag  that  isn't
+    // an introduce or join bag store the index of a child entry for each
+    // combination of tap positions at the primary substations shared with the
+    // child. In addition, each thread holds a projection of a table with its
+    // values and child indices while a bag is processed.
+    fn estimated_memory(&self) -> Option<usize> {
+        let entry_count = |bag_size: usize| TAP_COUNT.checked_pow(bag_size as u32).unwrap_or(usize::MAX);
+        let mut answer: usize = 0;
+        for bag_id in self.td.iter_v() {
+            let bag = self.td.v_attrs(&bag_id).unwrap();
+            answer = answer.saturating_add(entry_count(bag.vertices.len()).saturating_mul(TapsMemo::BYTES_PER_ENTRY));
+            let backpointer_entry_count = match bag.kind {
+                Some(BagKind::Leaf | BagKind::Introduce(_) | BagKind::Join) => 0,
+                Some(BagKind::Forget(_)) => entry_count(bag.vertices.len()),
+                None => self.td.iter_adjacent_out(&bag_id).unwrap().fold(0usize, |answer, child_id| {
+                    let child = self.td.v_attrs(&child_id).unwrap();
+                    answer.saturating_add(entry_count(bag.vertices.iter().filter(|x| child.vertices.contains(x)).count()))
+                }),
+            };
+            answer = answer.saturating_add(backpointer_entry_count.saturating_mul(mem::size_of::<usize>()));
+        }
+        Some(answer.saturating_add(
+            entry_count(self.td.max_bag_size)
+            .saturating_mul(TapsMemo::BYTES_PER_ENTRY + mem::size_of::<usize>())
+            .saturating_mul(self.thread_count.max(1))
+        ))
+    }
+
+    // A fixed pool of thread_count workers computes the memos of the bags  in
+    // any order in which children precede their parents. The memos don't depend
+    // on the order, so the solution is the same as with a single worker. The
+    // limits are checked between bags. The memos of a part of the bags  don't
+    // give any configuration, so the solver fails if it reaches its limits.
     fn solve(&mut self) -> Result<(), SolverError> {
-        // Memos
-        let memos: Arc<Mutex<HashMap<usize, TapsMemo>>> = Arc::new(Mutex::new(HashMap::new()));
-        // Find out the depth-first search postordering for the bags of self.td
-        let mut thread_data = self.td.dfs_postordering().into_iter().map(|id: usize| ThreadMetadata { bag_id: id, join_handle: None, tx: None }).collect_vec();
-        // Launch threads with the sliding window
-        let mut left_bound: usize = 0;
-        let mut right_bound = self.thread_count.min(thread_data.len()) - 1;
-        for bag_i in left_bound..=right_bound {
-            let input_clone: Arc<SwitchSelectionInstance> = self.input.clone();
-            let memos_clone: Arc<Mutex<HashMap<usize, TapsMemo>>> = memos.clone();
-            let dtd_clone: Arc<TreeDecomposition> = self.td.clone();
-            let bag_id_clone: usize = thread_data[bag_i].bag_id;
-            let (tx, rx) = mpsc::channel();
-            thread_data[bag_i].join_handle = Some(thread::spawn(move || thread_workload(input_clone, memos_clone, dtd_clone, bag_id_clone, rx)));
-            thread_data[bag_i].tx = Some(tx);
-        }
-        while left_bound <= right_bound {
-            thread_data[left_bound].join_handle.take().unwrap().join().unwrap()?;
-            left_bound += 1;
-            for bag_i in left_bound..=right_bound {
-                thread_data[bag_i].tx.as_ref().unwrap().send(thread_data[left_bound - 1].bag_id).unwrap_or(());
-            }
-            if right_bound + 1 < thread_data.len() {
-                right_bound += 1;
-                let input_clone: Arc<SwitchSelectionInstance> = self.input.clone();
-                let memos_clone: Arc<Mutex<HashMap<usize, TapsMemo>>> = memos.clone();
-                let dtd_clone: Arc<TreeDecomposition> = self.td.clone();
-                let bag_id_clone: usize = thread_data[right_bound].bag_id;
-                let (tx, rx) = mpsc::channel();
-                thread_data[right_bound].join_handle = Some(thread::spawn(move || thread_workload(input_clone, memos_clone, dtd_clone, bag_id_clone, rx)));
-                thread_data[right_bound].tx = Some(tx);
-                for bag_i in 0..left_bound {
-                    thread_data[right_bound].tx.as_ref().unwrap().send(thread_data[bag_i].bag_id).unwrap();
-                }
+        self.memos = None;
+        self.backpointers.clear();
+        self.memo_profiles.clear();
+        let deadline = self.limits.start();
+        if self.thread_count == 1 {
+            return self.solve_sequentially(&deadline);
+        }
+        let scheduler = Scheduler { state: Mutex::new(SchedulerState::new(&self.td)), bag_ready: Condvar::new() };
+        thread::scope(|scope| {
+            for _ in 0..self.thread_count {
+                scope.spawn(|| worker(&scheduler, &self.line_tap_pairs, &self.td, self.thread_count, &deadline));
             }
+        });
+        let state = scheduler.state.into_inner().unwrap();
+        if let Some(error) = state.error {
+            return Err(error);
         }
+        self.memo_profiles = state.memo_profiles;
+        self.backpointers = state.backpointers;
         // Save the memos
-        self.memos = Some(Arc::into_inner(memos).unwrap().into_inner().unwrap());
+        self.memos = Some(state.memos.into_iter().map(|(bag_id, memo)| (bag_id, Arc::into_inner(memo).unwrap())).collect());
         Ok(())
     }
+
+    fn set_limits(&mut self, limits: SolverLimits) -> Result<(), SolverError> {
+        self.limits = limits;
+        Ok(())
+    }
+}
+
+
+
+// TreeDecompositionSolver over a nice tree decomposition
+pub struct NiceTreeDecompositionSolver(TreeDecompositionSolver);
+
+// NiceTreeDecompositionSolver::NiceTreeDecompositionSolver
+impl NiceTreeDecompositionSolver {
+    #[inline]
+    pub fn new(input: SwitchSelectionInstance, td_parameters: &TreeDecompositionParameters) -> Result<Self, SolverError> {
+        Ok(NiceTreeDecompositionSolver(TreeDecompositionSolver::new(input, true, td_parameters)?))
+    }
+
+    #[inline]
+    pub fn set_thread_count(&mut self, thread_count: usize) {
+        self.0.set_thread_count(thread_count);
+    }
+}
+
+// NiceTreeDecompositionSolver::BaseSolver
+impl BaseSolver for NiceTreeDecompositionSolver {
+    fn with_input(input: SwitchSelectionInstance) -> Result<Self, SolverError> {
+        NiceTreeDecompositionSolver::new(input, &TreeDecompositionParameters::default())
+    }
+
+    fn get_solution(&self) -> Option<Solution> {
+        self.0.get_solution()
+    }
+
+    fn get_statistics(&self) -> SolverStatistics {
+        self.0.get_statistics()
+    }
+
+    fn get_tree_decomposition(&self) -> Option<&TreeDecomposition> {
+        self.0.get_tree_decomposition()
+    }
+
+    fn estimated_memory(&self) -> Option<usize> {
+        self.0.estimated_memory()
+    }
+
+    fn solve(&mut self) -> Result<(), SolverError> {
+        self.0.solve()
+    }
+
+    fn set_limits(&mut self, limits: SolverLimits) -> Result<(), SolverError> {
+        self.0.set_limits(limits)
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, mem, sync::{atomic::AtomicBool, Arc}};
+    use crabnets::ImmutableGraphContainer;
+    use itertools::Itertools;
+    use rand::Rng;
+    use rand_xoshiro::{Xoroshiro128PlusPlus, rand_core::SeedableRng};
+    use crate::{formats, solver::{base_solver::{BaseSolver, SolverLimits, TapValue}, benchmark::{generate_distribution_grid, small_grid_parameters}, brute_force_solver::BruteForceSolver, errors::SolverError, solution::Solution}, switch_selection_instance::SwitchSelectionInstance, tree_decomposition::{TreeDecomposition, TreeDecompositionAlgorithm, TreeDecompositionParameters}};
+    use super::{TapsMemo, TreeDecompositionSolver, INFEASIBLE};
+
+    const SAMPLE_COUNT: u64 = 30;
+
+    // Grids with 3 or 4 primary substations, small enough to try all combinations
+    // of tap positions
+    fn small_instance(seed: u64) -> SwitchSelectionInstance {
+        let parameters = small_grid_parameters(seed);
+        let treewidth = 1 + (seed / 2 % 2) as usize;
+        let primary_substation_count = 3 + (seed % 3 == 0) as usize;
+        SwitchSelectionInstance::new(generate_distribution_grid(treewidth, primary_substation_count, &parameters, seed)).unwrap()
+    }
+
+    fn brute_force(instance: &SwitchSelectionInstance) -> Option<TapValue> {
+        let mut solver = BruteForceSolver::with_input(instance.clone()).unwrap();
+        solver.solve().ok()?;
+        solver.get_solution().map(|x| x.objective.value)
+    }
+
+    fn solve(instance: &SwitchSelectionInstance, nice: bool, algorithm: TreeDecompositionAlgorithm, thread_count: usize) -> Option<Solution> {
+        let parameters = TreeDecompositionParameters { algorithm, ..TreeDecompositionParameters::default() };
+        let mut solver = TreeDecompositionSolver::new(instance.clone(), nice, &parameters).unwrap();
+        solver.set_thread_count(thread_count);
+        solver.solve().ok()?;
+        solver.get_solution()
+    }
+
+    fn random_memo(primary_substations: Vec<usize>, prng: &mut Xoroshiro128PlusPlus) -> TapsMemo {
+        let mut answer = TapsMemo::empty(primary_substations);
+        for value in answer.table.iter_mut() {
+            *value = if prng.gen_bool(0.2) { INFEASIBLE } else { prng.gen_range(0..=10) };
+        }
+        answer
+    }
+
+    #[test]
+    fn optimal_values_match_brute_force() {
+        for seed in 0..SAMPLE_COUNT {
+            let instance = small_instance(seed);
+            let expected = brute_force(&instance);
+            for (nice, algorithm) in [false, true].into_iter().cartesian_product([TreeDecompositionAlgorithm::Exact, TreeDecompositionAlgorithm::MinDegree]) {
+                let actual = solve(&instance, nice, algorithm, 1).map(|x| x.objective.value);
+                assert_eq!(actual, expected, "seed = {}, nice = {}, algorithm = {}", seed, nice, algorithm);
+            }
+        }
+    }
+
+    #[test]
+    fn reconstructed_solutions_are_feasible_and_optimal() {
+        for seed in 0..SAMPLE_COUNT {
+            let instance = small_instance(seed);
+            for nice in [false, true] {
+                if let Some(solution) = solve(&instance, nice, TreeDecompositionAlgorithm::Exact, 1) {
+                    let mut grid = instance.unwrap().clone();
+                    solution.render(&mut grid);
+                    assert_eq!(SwitchSelectionInstance::new(grid).unwrap().check_configuration().unwrap(), solution.objective.value, "seed = {}, nice = {}", seed, nice);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn solutions_do_not_depend_on_thread_count() {
+        for seed in 0..SAMPLE_COUNT {
+            let instance = small_instance(seed);
+            for nice in [false, true] {
+                let taps = |thread_count| solve(&instance, nice, TreeDecompositionAlgorithm::Exact, thread_count).map(|x| (x.tap_positions, x.objective.value));
+                assert_eq!(taps(1), taps(4), "seed = {}, nice = {}", seed, nice);
+            }
+        }
+    }
+
+    #[test]
+    fn single_primary_substation_grids_are_solved() {
+        let grid = formats::read_gnbs(&format!("{}/tests/fixtures/single_primary.gnbs", env!("CARGO_MANIFEST_DIR"))).unwrap();
+        let instance = SwitchSelectionInstance::new(grid).unwrap();
+        for nice in [false, true] {
+            let solution = solve(&instance, nice, TreeDecompositionAlgorithm::Exact, 1).unwrap();
+            assert_eq!(solution.tap_positions.into_iter().collect_vec(), vec![(0, 0)], "nice = {}", nice);
+            assert!(solution.cuts.is_empty(), "nice = {}", nice);
+        }
+    }
+
+    #[test]
+    fn imported_tree_decompositions_with_empty_bags_are_solved() {
+        let path = env::temp_dir().join(format!("switch_selection_empty_bag_{}.td", std::process::id()));
+        for seed in 0..SAMPLE_COUNT {
+            let instance = small_instance(seed);
+            let dg_kernel = instance.dg_kernel_for_switch_selection();
+            let td = TreeDecomposition::with_parameters(&dg_kernel, &TreeDecompositionParameters { algorithm: TreeDecompositionAlgorithm::MinDegree, ..TreeDecompositionParameters::default() }).unwrap();
+            // Put an empty bag at the root of the decomposition, above bag 1
+            let contents = formats::pace_tree_decomposition_string(&td, &dg_kernel);
+            let mut lines = contents.lines();
+            let header = lines.next().unwrap().split_whitespace().collect_vec();
+            let mut answer = format!("s td {} {} {}\nb 1\n", header[2].parse::<usize>().unwrap() + 1, header[3], header[4]);
+            for line in lines {
+                let tokens = line.split_whitespace().collect_vec();
+                let shifted = |x: &str| (x.parse::<usize>().unwrap() + 1).to_string();
+                answer += &match tokens[0] {
+                    "b" => format!("b {} {}\n", shifted(tokens[1]), tokens[2..].join(" ")),
+                    _ => format!("{} {}\n", shifted(tokens[0]), shifted(tokens[1])),
+                };
+            }
+            answer += "1 2\n";
+            fs::write(&path, answer).unwrap();
+            let parameters = TreeDecompositionParameters {
+                algorithm: TreeDecompositionAlgorithm::Imported,
+                input_path: Some(path.to_string_lossy().into_owned()),
+                ..TreeDecompositionParameters::default()
+            };
+            let expected = brute_force(&instance);
+            for nice in [false, true] {
+                let mut solver = TreeDecompositionSolver::new(instance.clone(), nice, &parameters).unwrap();
+                let actual = solver.solve().ok().and_then(|_| solver.get_solution()).map(|x| x.objective.value);
+                assert_eq!(actual, expected, "seed = {}, nice = {}", seed, nice);
+            }
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn zero_threads_solve_sequentially() {
+        for seed in 0..SAMPLE_COUNT {
+            let instance = small_instance(seed);
+            let taps = |thread_count| solve(&instance, false, TreeDecompositionAlgorithm::Exact, thread_count).map(|x| (x.tap_positions, x.objective.value));
+            assert_eq!(taps(0), taps(1), "seed = {}", seed);
+        }
+    }
+
+    #[test]
+    fn cancellation_stops_every_thread_count() {
+        let instance = small_instance(0);
+        for thread_count in [1, 4] {
+            let mut solver = TreeDecompositionSolver::new(instance.clone(), false, &TreeDecompositionParameters::default()).unwrap();
+            solver.set_thread_count(thread_count);
+            solver.set_limits(SolverLimits { time_limit: None, cancellation_token: Some(Arc::new(AtomicBool::new(true))) }).unwrap();
+            assert!(matches!(solver.solve(), Err(SolverError::Timeout { .. })), "thread_count = {}", thread_count);
+            assert!(solver.get_solution().is_none(), "thread_count = {}", thread_count);
+        }
+    }
+
+    #[test]
+    fn memory_estimate_covers_memos_and_backpointers() {
+        for seed in 0..SAMPLE_COUNT {
+            let instance = small_instance(seed);
+            for nice in [false, true] {
+                let mut solver = TreeDecompositionSolver::new(instance.clone(), nice, &TreeDecompositionParameters::default()).unwrap();
+                if solver.solve().is_err() {
+                    continue;
+                }
+                let memo_bytes: usize = solver.memos.as_ref().unwrap().values().map(|x| x.table.len() * TapsMemo::BYTES_PER_ENTRY).sum();
+                let backpointer_bytes: usize = solver.backpointers.values().flatten().filter_map(|x| x.child_indices.as_ref()).map(|x| x.len() * mem::size_of::<usize>()).sum();
+                assert!(solver.estimated_memory().unwrap() >= memo_bytes + backpointer_bytes, "seed = {}, nice = {}", seed, nice);
+            }
+        }
+    }
+
+    #[test]
+    fn memory_lower_bound_never_exceeds_the_estimate() {
+        for seed in 0..SAMPLE_COUNT {
+            let instance = small_instance(seed);
+            let dg_kernel = instance.dg_kernel_for_switch_selection();
+            for nice in [false, true] {
+                let solver = TreeDecompositionSolver::new(instance.clone(), nice, &TreeDecompositionParameters::default()).unwrap();
+                assert!(TreeDecomposition::width_lower_bound(&dg_kernel) + 1 <= solver.td.max_bag_size, "seed = {}, nice = {}", seed, nice);
+                let estimate = solver.estimated_memory().unwrap();
+                // A budget that fits the estimate passes both checks, and a budget
+                // below a single memo entry fails both
+                assert!(TreeDecompositionSolver::check_memory_lower_bound(&instance, "TreeDecompositionSolver", Some(estimate)).is_ok(), "seed = {}, nice = {}", seed, nice);
+                assert!(solver.check_memory_budget("TreeDecompositionSolver", Some(estimate)).is_ok(), "seed = {}, nice = {}", seed, nice);
+                assert!(matches!(TreeDecompositionSolver::check_memory_lower_bound(&instance, "TreeDecompositionSolver", Some(0)), Err(SolverError::ResourceLimit { .. })), "seed = {}, nice = {}", seed, nice);
+                assert!(matches!(solver.check_memory_budget("TreeDecompositionSolver", Some(0)), Err(SolverError::ResourceLimit { .. })), "seed = {}, nice = {}", seed, nice);
+            }
+        }
+    }
+
+    #[test]
+    fn parallel_memo_operations_match_sequential_ones() {
+        let mut prng = Xoroshiro128PlusPlus::seed_from_u64(13374);
+        // 21^4 entries are split into several chunks
+        let memo = random_memo(vec![1, 2, 3, 4], &mut prng);
+        let child = random_memo(vec![2, 4, 5, 6], &mut prng);
+        let (sequential, sequential_backpointers) = memo.forget(3, 1);
+        let (parallel, parallel_backpointers) = memo.forget(3, 4);
+        assert_eq!(sequential.table, parallel.table);
+        assert_eq!(sequential_backpointers.child_indices, parallel_backpointers.child_indices);
+        let mut sequential = memo.clone();
+        let mut parallel = memo.clone();
+        let sequential_backpointers = sequential.intersect(&child, 1);
+        let parallel_backpointers = parallel.intersect(&child, 4);
+        assert_eq!(sequential.table, parallel.table);
+        assert_eq!(sequential_backpointers.child_indices, parallel_backpointers.child_indices);
+    }
+
+    #[test]
+    fn backpointers_point_to_optimal_child_entries() {
+        let mut prng = Xoroshiro128PlusPlus::seed_from_u64(42);
+        let memo = random_memo(vec![1, 2, 3], &mut prng);
+        let child = random_memo(vec![2, 3, 4], &mut prng);
+        let mut parent = memo.clone();
+        let backpointers = parent.intersect(&child, 1);
+        for index in 0..parent.table.len() {
+            let child_index = backpointers.child_index(&parent, index);
+            // The child entry agrees with the parent entry on the common primary
+            // substations and attains the value the parent entry was combined with
+            assert_eq!(parent.taps_positions(index)[1..], child.taps_positions(child_index)[..2]);
+            assert_eq!(parent.table[index], memo.table[index].max(child.table[child_index]));
+        }
+        let (forgotten, backpointers) = memo.forget(2, 1);
+        for index in 0..forgotten.table.len() {
+            let child_index = backpointers.child_index(&forgotten, index);
+            assert_eq!(forgotten.table[index], memo.table[child_index]);
+            let taps_positions = memo.taps_positions(child_index);
+            assert_eq!(forgotten.taps_positions(index), vec![taps_positions[0], taps_positions[2]]);
+        }
+    }
 }
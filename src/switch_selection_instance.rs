@@ -1,7 +1,7 @@
 use std::{cmp::Ordering, collections::VecDeque, iter::once};
 use crabnets::{attributes::*, io::{AttributeCollectionIO, AttributeToken}, locales::*, topology_tests::TopologyTests, *};
 use itertools::Itertools;
-use crate::solver::errors::GraphError;
+use crate::solver::{base_solver::{TapValue, BASE_VOLTAGE_SQ}, errors::GraphError};
 
 
 
@@ -67,6 +67,9 @@ impl AttributeCollectionIO for DGVertexAttributes {
                     None
                 }
             },
+            "tap position" => if let StaticDispatchAttributeValue::Int8(value) = token.value {
+                self.tap_position = Some(value);
+            },
             _ => (),
         }
     }
@@ -120,6 +123,9 @@ impl AttributeCollectionIO for DGEdgeAttributes {
             "x" => if let StaticDispatchAttributeValue::Float64(value) = token.value {
                 self.x = value;
             },
+            "opened switch" => if let StaticDispatchAttributeValue::Bool(value) = token.value {
+                self.switch = value;
+            },
             _ => (),
         }
     }
@@ -140,11 +146,38 @@ pub struct SwitchSelectionInstance {
 impl SwitchSelectionInstance {
     pub fn new(mut graph: SwitchSelectionGraph) -> Result<Self, GraphError> {
         if !graph.is_connected() {
-            return Err(GraphError::from_str("The given ditribution grid is not connected."));
+            return Err(GraphError::InvalidTopology { description: "The given ditribution grid is not connected.".to_string(), vertex_ids: Vec::new() });
         }
         let mut unvisited_primary_substations: VecDeque<usize> = VecDeque::from_iter(graph.iter_v().filter(|x| graph.v_attrs(x).unwrap().tap_position.is_some()));
         if unvisited_primary_substations.is_empty() {
-            return Err(GraphError::from_str("The given distribution grid doesn't contain any primary substations."));
+            return Err(GraphError::InvalidTopology { description: "The given distribution grid doesn't contain any primary substations.".to_string(), vertex_ids: Vec::new() });
+        }
+        // Loads and impedances must be finite numbers
+        for substation_id in graph.iter_v().sorted() {
+            let attributes = graph.v_attrs(&substation_id).unwrap();
+            if !attributes.p.is_finite() || !attributes.q.is_finite() {
+                return Err(GraphError::MissingAttribute { description: format!("Substation {} has no finite load.", substation_id), vertex_ids: vec![substation_id] });
+            }
+        }
+        for edge in graph.iter_e() {
+            let attributes = graph.e_attrs(&edge.id1, &edge.id2, &edge.edge_id).unwrap();
+            if !attributes.r.is_finite() || !attributes.x.is_finite() {
+                return Err(GraphError::MissingAttribute {
+                    description: format!("The feeder between substations {} and {} has no finite impedance.", edge.id1, edge.id2),
+                    vertex_ids: vec![edge.id1, edge.id2],
+                });
+            }
+        }
+        // Each secondary substation must lie inside a line. The check is done for
+        // all of them upfront, as the depth-first search below only visits  the
+        // substations it reaches along lines.
+        for secondary_substation_id in graph.iter_v().filter(|x| graph.v_attrs(x).unwrap().tap_position.is_none()).sorted() {
+            if graph.iter_adjacent(&secondary_substation_id).unwrap().count() != 2 {
+                return Err(GraphError::InvalidTopology {
+                    description: format!("Secondary substation {} must have exactly 2 adjacent substations.", secondary_substation_id),
+                    vertex_ids: vec![secondary_substation_id],
+                });
+            }
         }
         // Launch depth-first search from each primary substation to  determine
         // which secondary substation belongs to a line between  which  primary
@@ -166,7 +199,10 @@ impl SwitchSelectionInstance {
                 if graph.v_attrs(&curr_substation_id).unwrap().tap_position.is_some() {
                     let line_endpoints = match primary_substation_id.cmp(&curr_substation_id) {
                         Ordering::Less => (primary_substation_id, curr_substation_id),
-                        Ordering::Equal => return Err(GraphError::from_string(format!("Primary substation {} has a feeder that begins and ends in it.", curr_substation_id))),
+                        Ordering::Equal => return Err(GraphError::InvalidTopology {
+                            description: format!("Primary substation {} has a feeder that begins and ends in it.", curr_substation_id),
+                            vertex_ids: curr_line.clone(),
+                        }),
                         Ordering::Greater => (curr_substation_id, primary_substation_id),
                     };
                     graph.e_attrs_mut(curr_line.last().unwrap(), &curr_line[curr_line.len() - 2], &0).unwrap().line_endpoints = Some(line_endpoints.clone());
@@ -177,12 +213,9 @@ impl SwitchSelectionInstance {
                     curr_line.resize(1, 0);
                     continue;
                 }
-                // If the current substation is a secondary substation, check that it has exactly 2
-                // neighbours and add the unvisited one on top of the stack.
+                // If the current substation is a secondary substation, add its unvisited  neighbour
+                // on top of the stack.
                 let adjacent_substations = graph.iter_adjacent(&curr_substation_id).unwrap().collect_vec();
-                if adjacent_substations.len() != 2 {
-                    return Err(GraphError::from_string(format!("Secondary substation {} must have exactly 2 adjacent substations.", curr_substation_id)));
-                }
                 unvisited_vertices_stack.push_front(if adjacent_substations[0] != curr_line[curr_line.len() - 2] { adjacent_substations[0] } else { adjacent_substations[1] });
             }
         }
@@ -203,6 +236,104 @@ impl SwitchSelectionInstance {
         }
         answer
     }
+
+    // Returns all lines of the grid. Each line is a sequence of substations  that
+    // begins in the primary substation with the smaller ID, ends in  the  primary
+    // substation with the larger ID and has only secondary substations  in  between.
+    pub fn lines(&self) -> Vec<Vec<usize>> {
+        let mut answer = Vec::new();
+        for primary_substation_id in self.graph.iter_v().filter(|x| self.graph.v_attrs(x).unwrap().tap_position.is_some()).sorted() {
+            for adjacent_id in self.graph.iter_adjacent(&primary_substation_id).unwrap().sorted() {
+                if self.graph.e_attrs(&primary_substation_id, &adjacent_id, &0).unwrap().line_endpoints.unwrap().0 != primary_substation_id {
+                    continue;
+                }
+                let mut line = Vec::from([primary_substation_id, adjacent_id]);
+                while self.graph.v_attrs(line.last().unwrap()).unwrap().tap_position.is_none() {
+                    let previous_substation_id = line[line.len() - 2];
+                    let next_substation_id = self.graph.iter_adjacent(line.last().unwrap()).unwrap().find(|&x| x != previous_substation_id).unwrap();
+                    line.push(next_substation_id);
+                }
+                answer.push(line);
+            }
+        }
+        answer
+    }
+
+    // Computes squared voltages at all substations of the line if the switch on
+    // the edge (line[cut_i], line[cut_i + 1]) is opened and the  endpoints  of
+    // the line have the given tap positions.
+    pub fn line_voltages_sq(&self, line: &[usize], cut_i: usize, left_tap_position: TapValue, right_tap_position: TapValue) -> Vec<f64> {
+        let mut answer = vec![0.0; line.len()];
+        // Left part of the line is fed from line[0]
+        answer[0] = BASE_VOLTAGE_SQ[(left_tap_position + 10) as usize];
+        let mut p: f64 = line[1..=cut_i].iter().map(|x| self.graph.v_attrs(x).unwrap().p).sum();
+        let mut q: f64 = line[1..=cut_i].iter().map(|x| self.graph.v_attrs(x).unwrap().q).sum();
+        for substation_i in 1..=cut_i {
+            let edge = self.graph.e_attrs(&line[substation_i - 1], &line[substation_i], &0).unwrap();
+            answer[substation_i] = answer[substation_i - 1] + edge.x * q - edge.r * p;
+            p -= self.graph.v_attrs(&line[substation_i]).unwrap().p;
+            q -= self.graph.v_attrs(&line[substation_i]).unwrap().q;
+        }
+        // Right part of the line is fed from line[line.len() - 1]
+        let last_i = line.len() - 1;
+        answer[last_i] = BASE_VOLTAGE_SQ[(right_tap_position + 10) as usize];
+        let mut p: f64 = line[(cut_i + 1)..last_i].iter().map(|x| self.graph.v_attrs(x).unwrap().p).sum();
+        let mut q: f64 = line[(cut_i + 1)..last_i].iter().map(|x| self.graph.v_attrs(x).unwrap().q).sum();
+        for substation_i in ((cut_i + 1)..last_i).rev() {
+            let edge = self.graph.e_attrs(&line[substation_i + 1], &line[substation_i], &0).unwrap();
+            answer[substation_i] = answer[substation_i + 1] + edge.x * q - edge.r * p;
+            p -= self.graph.v_attrs(&line[substation_i]).unwrap().p;
+            q -= self.graph.v_attrs(&line[substation_i]).unwrap().q;
+        }
+        answer
+    }
+
+    // Checks that the tap positions and the opened switches stored in the  grid
+    // form a feasible configuration: each line has exactly one  opened  switch,
+    // which makes the grid radial, and all squared voltages lie in [0.81, 1.21].
+    // Returns the value of the objective function for this configuration.
+    pub fn check_configuration(&self) -> Result<TapValue, GraphError> {
+        // Tolerance for the comparison of voltages
+        const EPSILON: f64 = 1e-9;
+        let mut objective_value: TapValue = 0;
+        for primary_substation_id in self.graph.iter_v().filter(|x| self.graph.v_attrs(x).unwrap().tap_position.is_some()) {
+            let tap_position = self.graph.v_attrs(&primary_substation_id).unwrap().tap_position.unwrap();
+            if !(-10..=10).contains(&tap_position) {
+                return Err(GraphError::InvalidConfiguration {
+                    description: format!("Tap position {} of primary substation {} is not in {{-10, ..., 10}}.", tap_position, primary_substation_id),
+                    vertex_ids: vec![primary_substation_id],
+                });
+            }
+            objective_value = objective_value.max(tap_position.abs());
+        }
+        for line in self.lines() {
+            let opened_switches = (0..(line.len() - 1)).filter(|&i| self.graph.e_attrs(&line[i], &line[i + 1], &0).unwrap().switch).collect_vec();
+            if opened_switches.len() != 1 {
+                return Err(GraphError::InvalidConfiguration {
+                    description: format!(
+                        "A line between primary substations {} and {} must have exactly 1 opened switch, but it has {}.",
+                        line[0], line.last().unwrap(), opened_switches.len()
+                    ),
+                    vertex_ids: line,
+                });
+            }
+            let voltages_sq = self.line_voltages_sq(
+                &line,
+                opened_switches[0],
+                self.graph.v_attrs(&line[0]).unwrap().tap_position.unwrap(),
+                self.graph.v_attrs(line.last().unwrap()).unwrap().tap_position.unwrap()
+            );
+            for (substation_i, voltage_sq) in voltages_sq.into_iter().enumerate() {
+                if voltage_sq < 0.81 - EPSILON || voltage_sq > 1.21 + EPSILON {
+                    return Err(GraphError::InvalidConfiguration {
+                        description: format!("Squared voltage {} at substation {} is not in [0.81, 1.21].", voltage_sq, line[substation_i]),
+                        vertex_ids: vec![line[substation_i]],
+                    });
+                }
+            }
+        }
+        Ok(objective_value)
+    }
 }
 
 // SwitchSelectionInstance::ImmutableGraphContainer
@@ -224,3 +355,113 @@ impl MutableGraphContainer for SwitchSelectionInstance {
         &mut self.graph
     }
 }
+
+
+
+#[cfg(test)]
+mod tests {
+    use crabnets::{BasicImmutableGraph, BasicMutableGraph};
+    use itertools::Itertools;
+    use crate::{formats, solver::errors::GraphError};
+    use super::{SwitchSelectionGraph, SwitchSelectionInstance};
+
+    // Reads a fixture grid from tests/fixtures the way the CLI reads its input.
+    // In all fixtures, secondary substations have p = 0.1 and q = 0.05,  and
+    // edges have r = x = 0.01.
+    fn load_grid(name: &str) -> SwitchSelectionGraph {
+        formats::read_gnbs(&format!("{}/tests/fixtures/{}.gnbs", env!("CARGO_MANIFEST_DIR"), name)).unwrap()
+    }
+
+    fn load(name: &str) -> Result<SwitchSelectionInstance, GraphError> {
+        SwitchSelectionInstance::new(load_grid(name))
+    }
+
+    fn load_error(name: &str) -> GraphError {
+        match load(name) {
+            Ok(_) => panic!("Fixture {} was accepted.", name),
+            Err(error) => error,
+        }
+    }
+
+    #[test]
+    fn disconnected_grids_are_rejected() {
+        let error = load_error("disconnected");
+        assert!(matches!(error, GraphError::InvalidTopology { .. }), "{:?}", error);
+        assert_eq!(error.to_string(), "The given ditribution grid is not connected.");
+    }
+
+    #[test]
+    fn grids_without_primary_substations_are_rejected() {
+        let error = load_error("no_primaries");
+        assert!(matches!(error, GraphError::InvalidTopology { .. }), "{:?}", error);
+        assert_eq!(error.to_string(), "The given distribution grid doesn't contain any primary substations.");
+    }
+
+    #[test]
+    fn non_finite_loads_are_rejected() {
+        // NaN can't be written in GNBS, so it's set after reading
+        let mut grid = load_grid("single_line");
+        grid.v_attrs_mut(&2).unwrap().p = f64::NAN;
+        let error = SwitchSelectionInstance::new(grid).err().unwrap();
+        assert!(matches!(&error, GraphError::MissingAttribute { vertex_ids, .. } if vertex_ids == &[2]), "{:?}", error);
+    }
+
+    #[test]
+    fn self_loop_feeders_are_rejected() {
+        let error = load_error("self_loop_feeder");
+        assert!(matches!(error, GraphError::InvalidTopology { .. }), "{:?}", error);
+        assert_eq!(error.to_string(), "Primary substation 0 has a feeder that begins and ends in it.");
+    }
+
+    #[test]
+    fn branching_secondary_substations_are_rejected() {
+        let error = load_error("branching_secondary");
+        assert!(matches!(&error, GraphError::InvalidTopology { vertex_ids, .. } if vertex_ids == &[3]), "{:?}", error);
+        let error = load_error("secondary_cycle");
+        assert!(matches!(&error, GraphError::InvalidTopology { vertex_ids, .. } if vertex_ids == &[3]), "{:?}", error);
+    }
+
+    #[test]
+    fn dangling_secondary_substations_are_rejected() {
+        let error = load_error("dangling_secondary");
+        assert!(matches!(&error, GraphError::InvalidTopology { vertex_ids, .. } if vertex_ids == &[3]), "{:?}", error);
+        assert_eq!(error.to_string(), "Secondary substation 3 must have exactly 2 adjacent substations.");
+    }
+
+    #[test]
+    fn defective_secondary_substations_are_found_before_feeders_are_traced() {
+        let error = load_error("self_loop_and_dangling");
+        assert!(matches!(&error, GraphError::InvalidTopology { vertex_ids, .. } if vertex_ids == &[5]), "{:?}", error);
+    }
+
+    #[test]
+    fn single_line_is_accepted() {
+        let instance = load("single_line").unwrap();
+        assert_eq!(instance.lines(), vec![vec![0, 2, 1]]);
+        assert_eq!(instance.v_attrs(&2).unwrap().line_endpoints, Some((0, 1)));
+        assert_eq!(instance.dg_kernel_for_switch_selection().count_e(), 1);
+    }
+
+    #[test]
+    fn parallel_feeders_are_accepted() {
+        let instance = load("parallel_feeders").unwrap();
+        assert_eq!(instance.lines(), vec![vec![0, 2, 1], vec![0, 3, 1]]);
+    }
+
+    #[test]
+    fn primary_only_grids_are_accepted() {
+        let instance = load("primary_pair").unwrap();
+        assert_eq!(instance.lines(), vec![vec![0, 1]]);
+        let instance = load("single_primary").unwrap();
+        assert!(instance.lines().is_empty());
+        assert_eq!(instance.check_configuration().unwrap(), 0);
+    }
+
+    #[test]
+    fn long_chains_are_accepted() {
+        let chain = (2..102).collect_vec();
+        let instance = load("long_chain").unwrap();
+        assert_eq!(instance.lines(), vec![[vec![0], chain.clone(), vec![1]].concat()]);
+        assert!(chain.iter().all(|x| instance.v_attrs(x).unwrap().line_endpoints == Some((0, 1))));
+    }
+}
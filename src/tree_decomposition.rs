@@ -1,32 +1,120 @@
-use std::collections::VecDeque;
+use std::{collections::{HashMap, HashSet, VecDeque}, fmt::Display, io, str::FromStr, sync::mpsc, thread, time::Duration};
 use arboretum_td::{exact::TamakiPid, graph::{HashMapGraph, MutableGraph as ArboretumMutableGraph}, solver::{AtomSolver, ComputationResult}};
 use crabnets::{*, attributes::*, locales::*};
 use itertools::Itertools;
-use crate::{switch_selection_instance::SwitchSelectionGraph, solver::errors::GraphError};
+use crate::{formats::read_pace_tree_decomposition, switch_selection_instance::SwitchSelectionGraph, solver::errors::{GraphError, UsageError}};
 
 
 
 
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TreeDecompositionAlgorithm {
+    // Optimal tree decomposition computed by TamakiPid
+    #[default]
+    Exact,
+    // Elimination ordering that eliminates a vertex of minimum degree first
+    MinDegree,
+    // Elimination ordering that eliminates a vertex whose neighbourhood needs
+    // the fewest edges to become a clique first
+    MinFill,
+    // Tree decomposition loaded from a file
+    Imported,
+}
+
+// TreeDecompositionAlgorithm::FromStr
+impl FromStr for TreeDecompositionAlgorithm {
+    type Err = UsageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "exact" => Ok(TreeDecompositionAlgorithm::Exact),
+            "min-degree" => Ok(TreeDecompositionAlgorithm::MinDegree),
+            "min-fill" => Ok(TreeDecompositionAlgorithm::MinFill),
+            _ => Err(UsageError::from_string(format!("Unknown tree decomposition algorithm {}.", s))),
+        }
+    }
+}
+
+// TreeDecompositionAlgorithm::Display
+impl Display for TreeDecompositionAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TreeDecompositionAlgorithm::Exact => write!(f, "exact"),
+            TreeDecompositionAlgorithm::MinDegree => write!(f, "min-degree"),
+            TreeDecompositionAlgorithm::MinFill => write!(f, "min-fill"),
+            TreeDecompositionAlgorithm::Imported => write!(f, "imported"),
+        }
+    }
+}
+
+
+
+#[derive(Clone, Debug, Default)]
+pub struct TreeDecompositionParameters {
+    pub algorithm: TreeDecompositionAlgorithm,
+    // If the exact algorithm doesn't finish in time, the best  decomposition
+    // found by the heuristics is used instead. The exact algorithm can't  be
+    // stopped, so its thread keeps running until it finishes.
+    pub time_limit: Option<Duration>,
+    // Tree decomposition in PACE .td format to use instead of computing one
+    pub input_path: Option<String>,
+}
+
+
+
+// Role of a bag in a nice tree decomposition
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BagKind {
+    // An empty bag without children
+    Leaf,
+    // The bag of the only child plus the given vertex
+    Introduce(usize),
+    // The bag of the only child minus the given vertex
+    Forget(usize),
+    // The same bag as each of the two children
+    Join,
+}
+
+// BagKind::Display
+impl Display for BagKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BagKind::Leaf => write!(f, "leaf"),
+            BagKind::Introduce(vertex) => write!(f, "introduce {}", vertex),
+            BagKind::Forget(vertex) => write!(f, "forget {}", vertex),
+            BagKind::Join => write!(f, "join"),
+        }
+    }
+}
+
+
+
 #[derive(Clone, Default)]
 pub struct Bag {
     pub vertices: Vec<usize>,
+    // None unless the tree decomposition is nice
+    pub kind: Option<BagKind>,
 }
 
 // Bag::AttributeCollection
 impl AttributeCollection for Bag {
     fn new() -> Self {
-        Bag { vertices: Vec::new() }
+        Bag { vertices: Vec::new(), kind: None }
     }
 }
 
 
 
+type BagGraph = graph!(A ---X--> A with VertexAttributeCollectionType = Bag);
+
 #[derive(Clone, Default)]
 pub struct TreeDecomposition {
-    graph: graph!(A ---X--> A with VertexAttributeCollectionType = Bag),
+    graph: BagGraph,
     pub max_bag_size: usize,
     pub root_id: usize,
+    // Algorithm that actually produced the decomposition
+    pub algorithm: TreeDecompositionAlgorithm,
 }
 
 // TreeDecomposition::TreeDecomposition
@@ -45,7 +133,180 @@ impl TreeDecomposition {
         answer
     }
 
+    #[inline]
+    pub fn is_nice(&self) -> bool {
+        self.v_attrs(&self.root_id).unwrap().kind.is_some()
+    }
+
+    // Converts the tree decomposition into a nice one with an empty root bag.
+    // The width doesn't change.
+    pub fn to_nice(&self) -> TreeDecomposition {
+        let mut answer: BagGraph = Graph::new();
+        let subtree_root_id = self.add_nice_subtree(self.root_id, &mut answer);
+        // Forget the vertices of the root bag one by one
+        let root_id = Self::add_nice_path(&mut answer, subtree_root_id, &self.v_attrs(&self.root_id).unwrap().vertices, &[]);
+        TreeDecomposition { graph: answer, max_bag_size: self.max_bag_size, root_id, algorithm: self.algorithm }
+    }
+
+    fn add_nice_bag(answer: &mut BagGraph, vertices: Vec<usize>, kind: BagKind, children: &[usize]) -> usize {
+        let bag_id = answer.count_v();
+        answer.add_v(Some(bag_id));
+        *answer.v_attrs_mut(&bag_id).unwrap() = Bag { vertices, kind: Some(kind) };
+        for child_id in children {
+            answer.add_e(&bag_id, child_id, true, None).unwrap();
+        }
+        bag_id
+    }
+
+    // Adds forget bags and then introduce bags on top of the bag  with  ID
+    // bag_id to turn its vertices into to. Returns the ID of the topmost bag.
+    fn add_nice_path(answer: &mut BagGraph, mut bag_id: usize, from: &[usize], to: &[usize]) -> usize {
+        let mut vertices = from.to_vec();
+        for &vertex in from.iter().filter(|x| !to.contains(x)) {
+            vertices.retain(|&x| x != vertex);
+            bag_id = Self::add_nice_bag(answer, vertices.clone(), BagKind::Forget(vertex), &[bag_id]);
+        }
+        for &vertex in to.iter().filter(|x| !from.contains(x)) {
+            vertices.insert(vertices.binary_search(&vertex).unwrap_err(), vertex);
+            bag_id = Self::add_nice_bag(answer, vertices.clone(), BagKind::Introduce(vertex), &[bag_id]);
+        }
+        bag_id
+    }
+
+    // Builds a nice tree decomposition of the subtree rooted at the bag  with
+    // ID bag_id. Returns the ID of its root, which has the same vertices.
+    fn add_nice_subtree(&self, bag_id: usize, answer: &mut BagGraph) -> usize {
+        let vertices = &self.v_attrs(&bag_id).unwrap().vertices;
+        let mut branch_ids = Vec::new();
+        for child_id in self.iter_adjacent_out(&bag_id).unwrap().sorted() {
+            let child_subtree_root_id = self.add_nice_subtree(child_id, answer);
+            branch_ids.push(Self::add_nice_path(answer, child_subtree_root_id, &self.v_attrs(&child_id).unwrap().vertices, vertices));
+        }
+        if branch_ids.is_empty() {
+            let leaf_id = Self::add_nice_bag(answer, Vec::new(), BagKind::Leaf, &[]);
+            branch_ids.push(Self::add_nice_path(answer, leaf_id, &[], vertices));
+        }
+        // Join the branches pairwise
+        let mut answer_id = branch_ids[0];
+        for &branch_id in branch_ids[1..].iter() {
+            answer_id = Self::add_nice_bag(answer, vertices.clone(), BagKind::Join, &[answer_id, branch_id]);
+        }
+        answer_id
+    }
+
+    #[inline]
     pub fn for_switch_selection_graph(graph: &SwitchSelectionGraph) -> Result<TreeDecomposition, GraphError> {
+        Self::with_parameters(graph, &TreeDecompositionParameters::default())
+    }
+
+    pub fn with_parameters(graph: &SwitchSelectionGraph, parameters: &TreeDecompositionParameters) -> Result<TreeDecomposition, GraphError> {
+        if graph.count_v() == 0 {
+            return Err(GraphError::InvalidTopology { description: "Failed to compute a tree decomposition of an empty graph.".to_string(), vertex_ids: Vec::new() });
+        }
+        if let Some(path) = &parameters.input_path {
+            return read_pace_tree_decomposition(path, graph);
+        }
+        match parameters.algorithm {
+            TreeDecompositionAlgorithm::Exact => match Self::exact(graph, parameters.time_limit) {
+                Some(value) => Ok(value),
+                None => Ok(Self::best_heuristic(graph)),
+            },
+            TreeDecompositionAlgorithm::Imported => Err(GraphError::Io { description: "No file to import the tree decomposition from.".to_string(), source: Box::new(io::Error::from(io::ErrorKind::NotFound)) }),
+            heuristic => Ok(Self::from_elimination_ordering(graph, heuristic)),
+        }
+    }
+
+    // Builds a tree decomposition out of a list of bags and undirected  edges
+    // between their indices. The first bag becomes the root.
+    pub fn from_bags(bags: Vec<Vec<usize>>, edges: &[(usize, usize)], algorithm: TreeDecompositionAlgorithm) -> Result<TreeDecomposition, GraphError> {
+        if bags.is_empty() {
+            return Err(GraphError::InvalidTopology { description: "The tree decomposition has no bags.".to_string(), vertex_ids: Vec::new() });
+        }
+        if edges.len() != bags.len() - 1 {
+            return Err(GraphError::InvalidTopology { description: format!("The tree decomposition has {} bags and {} edges, so it's not a tree.", bags.len(), edges.len()), vertex_ids: Vec::new() });
+        }
+        let mut adjacency: Vec<Vec<usize>> = vec![Vec::new(); bags.len()];
+        for &(bag_i, bag_j) in edges.iter() {
+            if bag_i >= bags.len() || bag_j >= bags.len() {
+                return Err(GraphError::InvalidTopology { description: format!("The edge {{{}, {}}} of the tree decomposition connects non-existent bags.", bag_i, bag_j), vertex_ids: vec![bag_i, bag_j] });
+            }
+            adjacency[bag_i].push(bag_j);
+            adjacency[bag_j].push(bag_i);
+        }
+        let mut answer: BagGraph = Graph::new();
+        let mut max_bag_size: usize = 0;
+        for (bag_id, vertices) in bags.into_iter().enumerate() {
+            let vertices = vertices.into_iter().sorted().dedup().collect_vec();
+            max_bag_size = max_bag_size.max(vertices.len());
+            answer.add_v(Some(bag_id));
+            answer.v_attrs_mut(&bag_id).unwrap().vertices = vertices;
+        }
+        // Orient the edges away from the root
+        let mut visited = vec![false; adjacency.len()];
+        visited[0] = true;
+        let mut unvisited_bags = VecDeque::from([0]);
+        while let Some(curr_bag_id) = unvisited_bags.pop_front() {
+            for &adjacent_bag_id in adjacency[curr_bag_id].iter() {
+                if !visited[adjacent_bag_id] {
+                    visited[adjacent_bag_id] = true;
+                    answer.add_e(&curr_bag_id, &adjacent_bag_id, true, None).unwrap();
+                    unvisited_bags.push_back(adjacent_bag_id);
+                }
+            }
+        }
+        if visited.iter().any(|&x| !x) {
+            return Err(GraphError::InvalidTopology { description: "The tree decomposition is not connected, so it's not a tree.".to_string(), vertex_ids: Vec::new() });
+        }
+        Ok(TreeDecomposition { graph: answer, max_bag_size, root_id: 0, algorithm })
+    }
+
+    // Checks that the bags cover all vertices and edges of the graph and that
+    // the bags containing each vertex form a subtree.
+    pub fn validate(&self, graph: &SwitchSelectionGraph) -> Result<(), GraphError> {
+        let graph_vertices: HashSet<usize> = graph.iter_v().collect();
+        let parents: HashMap<usize, usize> = self.iter_e().map(|x| (x.id2, x.id1)).collect();
+        let mut containing_bag_count: HashMap<usize, usize> = HashMap::new();
+        let mut subtree_root_count: HashMap<usize, usize> = HashMap::new();
+        for bag_id in self.iter_v() {
+            let vertices = &self.v_attrs(&bag_id).unwrap().vertices;
+            let parent_vertices = parents.get(&bag_id).map(|x| &self.v_attrs(x).unwrap().vertices);
+            for vertex in vertices.iter() {
+                if !graph_vertices.contains(vertex) {
+                    return Err(GraphError::InvalidTopology { description: format!("Bag {} contains vertex {}, which isn't in the graph.", bag_id, vertex), vertex_ids: vec![*vertex] });
+                }
+                *containing_bag_count.entry(*vertex).or_insert(0) += 1;
+                if parent_vertices.map_or(true, |x| x.binary_search(vertex).is_err()) {
+                    *subtree_root_count.entry(*vertex).or_insert(0) += 1;
+                }
+            }
+        }
+        for vertex in graph.iter_v() {
+            if !containing_bag_count.contains_key(&vertex) {
+                return Err(GraphError::InvalidTopology { description: format!("Vertex {} isn't contained in any bag.", vertex), vertex_ids: vec![vertex] });
+            }
+            // The bags containing the vertex form a subtree iff exactly one of  them
+            // has a parent without the vertex
+            if subtree_root_count[&vertex] != 1 {
+                return Err(GraphError::InvalidTopology { description: format!("The bags containing vertex {} don't form a subtree.", vertex), vertex_ids: vec![vertex] });
+            }
+        }
+        for edge in graph.iter_e().filter(|x| x.id1 != x.id2) {
+            let covered = self.iter_v().any(|x| {
+                let vertices = &self.v_attrs(&x).unwrap().vertices;
+                vertices.binary_search(&edge.id1).is_ok() && vertices.binary_search(&edge.id2).is_ok()
+            });
+            if !covered {
+                return Err(GraphError::InvalidTopology { description: format!("Edge {{{}, {}}} isn't contained in any bag.", edge.id1, edge.id2), vertex_ids: vec![edge.id1, edge.id2] });
+            }
+        }
+        Ok(())
+    }
+
+    // Returns None if TamakiPid fails or runs out of time. In the latter case
+    // its thread is left running in the background: TamakiPid has no way to be
+    // interrupted, and the thread can't be joined without waiting  for  it  to
+    // finish. Library callers must opt in, see SolverOptions.
+    fn exact(graph: &SwitchSelectionGraph, time_limit: Option<Duration>) -> Option<TreeDecomposition> {
         let mut arboretum_graph = HashMapGraph::new();
         for id in graph.iter_v() {
             arboretum_graph.add_vertex(id);
@@ -53,9 +314,22 @@ impl TreeDecomposition {
         for edge in graph.iter_e() {
             arboretum_graph.add_edge(edge.id1, edge.id2);
         }
-        match TamakiPid::with_graph(&arboretum_graph).compute() {
+        let vertices = graph.iter_v().sorted().collect_vec();
+        let compute = move || Self::from_arboretum(TamakiPid::with_graph(&arboretum_graph).compute(), vertices);
+        match time_limit {
+            Some(value) => {
+                let (tx, rx) = mpsc::channel();
+                thread::spawn(move || tx.send(compute()).unwrap_or(()));
+                rx.recv_timeout(value).ok().flatten()
+            },
+            None => compute(),
+        }
+    }
+
+    fn from_arboretum(result: ComputationResult, vertices: Vec<usize>) -> Option<TreeDecomposition> {
+        match result {
             ComputationResult::ComputedTreeDecomposition(td) => {
-                let mut answer: graph!(A ---X--> A with VertexAttributeCollectionType = Bag) = Graph::new();
+                let mut answer: BagGraph = Graph::new();
                 for bag in td.bags() {
                     let vertex_set = Vec::from_iter(bag.vertex_set.iter().sorted().cloned());
                     answer.add_v(Some(bag.id));
@@ -72,20 +346,112 @@ impl TreeDecomposition {
                         }
                     }
                 }
-                Ok(TreeDecomposition { graph: answer, max_bag_size: td.max_bag_size, root_id: tree_decomposition_root_id })
+                Some(TreeDecomposition { graph: answer, max_bag_size: td.max_bag_size, root_id: tree_decomposition_root_id, algorithm: TreeDecompositionAlgorithm::Exact })
             },
             ComputationResult::Bounds(bounds) => {
-                if bounds.lowerbound == graph.count_v() - 1 {
-                    let mut answer: graph!(A ---X--> A with VertexAttributeCollectionType = Bag) = Graph::new();
+                if bounds.lowerbound == vertices.len() - 1 {
+                    let mut answer: BagGraph = Graph::new();
                     answer.add_v(Some(0));
-                    answer.v_attrs_mut(&0).unwrap().vertices = graph.iter_v().sorted().collect();
-                    Ok(TreeDecomposition { graph: answer, max_bag_size: graph.count_v(), root_id: 0 })
+                    let max_bag_size = vertices.len();
+                    answer.v_attrs_mut(&0).unwrap().vertices = vertices;
+                    Some(TreeDecomposition { graph: answer, max_bag_size, root_id: 0, algorithm: TreeDecompositionAlgorithm::Exact })
                 } else {
-                    Err(GraphError::from_str("Failed to compute a tree decomposition."))
+                    None
                 }
             },
         }
     }
+
+    // Runs all heuristics and returns the decomposition of the lowest width
+    fn best_heuristic(graph: &SwitchSelectionGraph) -> TreeDecomposition {
+        [TreeDecompositionAlgorithm::MinFill, TreeDecompositionAlgorithm::MinDegree]
+            .into_iter()
+            .map(|x| Self::from_elimination_ordering(graph, x))
+            .min_by_key(|x| x.max_bag_size)
+            .unwrap()
+    }
+
+    // Lower bound on the treewidth of the graph: its degeneracy, i.e. the largest
+    // minimum degree of its subgraphs, found by removing a vertex of  minimum
+    // degree one by one. Unlike the heuristics, it adds no edges, so it takes
+    // a fraction of the time of any decomposition.
+    pub fn width_lower_bound(graph: &SwitchSelectionGraph) -> usize {
+        let mut adjacency: HashMap<usize, HashSet<usize>> = graph
+            .iter_v()
+            .map(|x| (x, graph.iter_adjacent(&x).unwrap().filter(|&y| y != x).collect()))
+            .collect();
+        let mut answer: usize = 0;
+        while !adjacency.is_empty() {
+            let vertex = *adjacency.iter().min_by_key(|&(&x, neighbours)| (neighbours.len(), x)).unwrap().0;
+            let neighbours = adjacency.remove(&vertex).unwrap();
+            answer = answer.max(neighbours.len());
+            for neighbour in neighbours.iter() {
+                adjacency.get_mut(neighbour).unwrap().remove(&vertex);
+            }
+        }
+        answer
+    }
+
+    // Number of edges that must be added to make the neighbourhood a clique
+    fn fill_in(adjacency: &HashMap<usize, HashSet<usize>>, neighbours: &HashSet<usize>) -> usize {
+        neighbours
+            .iter()
+            .tuple_combinations()
+            .filter(|&(x, y)| !adjacency[x].contains(y))
+            .count()
+    }
+
+    // Eliminates the vertices one by one in the order chosen by the heuristic.
+    // The i-th bag consists of the i-th eliminated vertex and  its  neighbours
+    // at the moment of elimination, and its parent is the bag of the neighbour
+    // eliminated first. Ties are broken by vertex IDs to make the  result
+    // reproducible.
+    fn from_elimination_ordering(graph: &SwitchSelectionGraph, heuristic: TreeDecompositionAlgorithm) -> TreeDecomposition {
+        let mut adjacency: HashMap<usize, HashSet<usize>> = graph
+            .iter_v()
+            .map(|x| (x, graph.iter_adjacent(&x).unwrap().filter(|&y| y != x).collect()))
+            .collect();
+        let mut elimination_ordering: Vec<usize> = Vec::with_capacity(adjacency.len());
+        let mut bags: Vec<Vec<usize>> = Vec::with_capacity(adjacency.len());
+        while !adjacency.is_empty() {
+            let vertex = match heuristic {
+                TreeDecompositionAlgorithm::MinFill => *adjacency
+                    .iter()
+                    .min_by_key(|&(&x, neighbours)| (Self::fill_in(&adjacency, neighbours), neighbours.len(), x))
+                    .unwrap()
+                    .0,
+                _ => *adjacency.iter().min_by_key(|&(&x, neighbours)| (neighbours.len(), x)).unwrap().0,
+            };
+            let neighbours = adjacency.remove(&vertex).unwrap();
+            for neighbour in neighbours.iter() {
+                let neighbour_adjacency = adjacency.get_mut(neighbour).unwrap();
+                neighbour_adjacency.remove(&vertex);
+                neighbour_adjacency.extend(neighbours.iter().filter(|&x| x != neighbour));
+            }
+            elimination_ordering.push(vertex);
+            bags.push(neighbours.into_iter().chain([vertex]).sorted().collect());
+        }
+        let elimination_index: HashMap<usize, usize> = elimination_ordering.iter().enumerate().map(|(i, &x)| (x, i)).collect();
+        let mut answer: BagGraph = Graph::new();
+        let root_id = bags.len() - 1;
+        let max_bag_size = bags.iter().map(|x| x.len()).max().unwrap();
+        for (bag_id, vertices) in bags.iter().enumerate() {
+            answer.add_v(Some(bag_id));
+            answer.v_attrs_mut(&bag_id).unwrap().vertices = vertices.clone();
+        }
+        for (bag_id, vertices) in bags.iter().enumerate().take(root_id) {
+            // Bags of different connected components are attached to the root, which
+            // doesn't break the running intersection property
+            let parent_id = vertices
+                .iter()
+                .filter(|&&x| x != elimination_ordering[bag_id])
+                .map(|x| elimination_index[x])
+                .min()
+                .unwrap_or(root_id);
+            answer.add_e(&parent_id, &bag_id, true, None).unwrap();
+        }
+        TreeDecomposition { graph: answer, max_bag_size, root_id, algorithm: heuristic }
+    }
 }
 
 // TreeDecomposition::ImmutableGraphContainer
@@ -0,0 +1,413 @@
+use std::{collections::{HashMap, VecDeque}, fs, io, path::Path};
+use crabnets::{BasicImmutableGraph, ImmutableGraphContainer};
+use itertools::Itertools;
+use crate::{solver::errors::UsageError, switch_selection_instance::SwitchSelectionGraph, tree_decomposition::TreeDecomposition};
+
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VisualisationFormat {
+    DOT,
+    GraphML,
+}
+
+// VisualisationFormat::VisualisationFormat
+impl VisualisationFormat {
+    pub fn from_path(path: &str) -> Result<Self, UsageError> {
+        match Path::new(path).extension().and_then(|x| x.to_str()) {
+            Some("dot") | Some("gv") => Ok(VisualisationFormat::DOT),
+            Some("graphml") => Ok(VisualisationFormat::GraphML),
+            _ => Err(UsageError::from_string(format!("Can't deduce a visualisation format from the extension of {}: expected .dot, .gv or .graphml.", path))),
+        }
+    }
+}
+
+
+
+// Colours of the primary substations and of the substations they feed. The
+// i-th primary substation in the ascending order of IDs gets PALETTE[i % 12].
+const PALETTE: [(u8, u8, u8); 12] = [
+    (0x1f, 0x77, 0xb4), (0xff, 0x7f, 0x0e), (0x2c, 0xa0, 0x2c), (0xd6, 0x27, 0x28),
+    (0x94, 0x67, 0xbd), (0x8c, 0x56, 0x4b), (0xe3, 0x77, 0xc2), (0x7f, 0x7f, 0x7f),
+    (0xbc, 0xbd, 0x22), (0x17, 0xbe, 0xcf), (0xae, 0xc7, 0xe8), (0xff, 0xbb, 0x78),
+];
+const NEUTRAL_COLOUR: (u8, u8, u8) = (0xd0, 0xd0, 0xd0);
+const OPENED_SWITCH_COLOUR: (u8, u8, u8) = (0xe0, 0x00, 0x00);
+
+
+
+#[derive(Clone, Debug)]
+enum DrawingValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Text(String),
+}
+
+// DrawingValue::DrawingValue
+impl DrawingValue {
+    fn graphml_type(&self) -> &'static str {
+        match self {
+            DrawingValue::Bool(_) => "boolean",
+            DrawingValue::Int(_) => "long",
+            DrawingValue::Float(_) => "double",
+            DrawingValue::Text(_) => "string",
+        }
+    }
+
+    fn to_plain_string(&self) -> String {
+        match self {
+            DrawingValue::Bool(value) => value.to_string(),
+            DrawingValue::Int(value) => value.to_string(),
+            DrawingValue::Float(value) => value.to_string(),
+            DrawingValue::Text(value) => value.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct DrawingVertex {
+    id: usize,
+    label: String,
+    shape: &'static str,
+    colour: (u8, u8, u8),
+    data: Vec<(&'static str, DrawingValue)>,
+}
+
+#[derive(Clone, Debug)]
+struct DrawingEdge {
+    source: usize,
+    target: usize,
+    label: Option<String>,
+    colour: (u8, u8, u8),
+    dashed: bool,
+    data: Vec<(&'static str, DrawingValue)>,
+}
+
+// A graph with labelled and coloured vertices and edges that can be rendered
+// in any of the visualisation formats
+#[derive(Clone, Debug)]
+pub struct Drawing {
+    name: &'static str,
+    directed: bool,
+    vertices: Vec<DrawingVertex>,
+    edges: Vec<DrawingEdge>,
+}
+
+// Drawing::Drawing
+impl Drawing {
+    // Vertices are coloured by the primary substation that feeds them  and
+    // opened switches are drawn as dashed red edges. Every substation is fed by
+    // the closest primary substation it's connected to without passing opened
+    // switches: in a solution it's the only such primary substation.
+    pub fn for_grid(grid: &SwitchSelectionGraph) -> Drawing {
+        let colours = primary_substation_colours(grid);
+        let feeders = feeding_primary_substations(grid);
+        let vertices = grid.iter_v().sorted().map(|id| {
+            let attributes = grid.v_attrs(&id).unwrap();
+            let mut data = vec![("p", DrawingValue::Float(attributes.p)), ("q", DrawingValue::Float(attributes.q))];
+            let (label, shape) = match attributes.tap_position {
+                Some(tap_position) => {
+                    data.push(("tap_position", DrawingValue::Int(tap_position as i64)));
+                    (format!("{}\ntap {}", id, tap_position), "box")
+                },
+                None => (id.to_string(), "ellipse"),
+            };
+            if let Some(&feeder) = feeders.get(&id) {
+                data.push(("feeder", DrawingValue::Int(feeder as i64)));
+            }
+            let colour = feeders.get(&id).map(|x| colours[x]).unwrap_or(NEUTRAL_COLOUR);
+            DrawingVertex { id, label, shape, colour, data }
+        }).collect_vec();
+        let edges = grid.iter_e().map(|x| (x.id1.min(x.id2), x.id1.max(x.id2))).sorted().map(|(id1, id2)| {
+            let attributes = grid.e_attrs(&id1, &id2, &0).unwrap();
+            let data = vec![
+                ("resistance", DrawingValue::Float(attributes.r)),
+                ("reactance", DrawingValue::Float(attributes.x)),
+                ("opened_switch", DrawingValue::Bool(attributes.switch)),
+            ];
+            let colour = match (attributes.switch, feeders.get(&id1), feeders.get(&id2)) {
+                (true, _, _) => OPENED_SWITCH_COLOUR,
+                (false, Some(feeder1), Some(feeder2)) if feeder1 == feeder2 => colours[feeder1],
+                _ => NEUTRAL_COLOUR,
+            };
+            DrawingEdge { source: id1, target: id2, label: attributes.switch.then(|| "open".to_string()), colour, dashed: attributes.switch, data }
+        }).collect_vec();
+        Drawing { name: "grid", directed: false, vertices, edges }
+    }
+
+    // All vertices of the DG-kernel are primary substations. They get the same
+    // colours as in the drawing of the grid and, if the grid is a  solution,
+    // its tap positions.
+    pub fn for_dg_kernel(dg_kernel: &SwitchSelectionGraph, grid: &SwitchSelectionGraph) -> Drawing {
+        let colours = primary_substation_colours(grid);
+        let vertices = dg_kernel.iter_v().sorted().map(|id| {
+            let mut data = Vec::new();
+            let label = match grid.v_attrs(&id).unwrap().tap_position {
+                Some(value) => {
+                    data.push(("tap_position", DrawingValue::Int(value as i64)));
+                    format!("{}\ntap {}", id, value)
+                },
+                None => id.to_string(),
+            };
+            DrawingVertex { id, label, shape: "box", colour: colours.get(&id).copied().unwrap_or(NEUTRAL_COLOUR), data }
+        }).collect_vec();
+        let edges = dg_kernel.iter_e().map(|x| (x.id1.min(x.id2), x.id1.max(x.id2))).sorted().map(|(id1, id2)|
+            DrawingEdge { source: id1, target: id2, label: None, colour: NEUTRAL_COLOUR, dashed: false, data: Vec::new() }
+        ).collect_vec();
+        Drawing { name: "dg_kernel", directed: false, vertices, edges }
+    }
+
+    // Bags point to their children. Each bag is labelled with its vertices, its
+    // kind if the tree decomposition is nice and the number of entries  in  its
+    // memo if memo_sizes has one for it.
+    pub fn for_tree_decomposition(td: &TreeDecomposition, memo_sizes: &[(usize, usize)]) -> Drawing {
+        let memo_sizes: HashMap<usize, usize> = memo_sizes.iter().copied().collect();
+        let vertices = td.iter_v().sorted().map(|id| {
+            let bag = td.v_attrs(&id).unwrap();
+            let vertex_list = bag.vertices.iter().join(", ");
+            let mut label = format!("bag {}\n{{{}}}", id, vertex_list);
+            let mut data = vec![("vertices", DrawingValue::Text(vertex_list)), ("bag_size", DrawingValue::Int(bag.vertices.len() as i64))];
+            if let Some(kind) = bag.kind {
+                label += &format!("\n{}", kind);
+                data.push(("kind", DrawingValue::Text(kind.to_string())));
+            }
+            if let Some(&entries) = memo_sizes.get(&id) {
+                label += &format!("\n{} memo entries", entries);
+                data.push(("memo_entries", DrawingValue::Int(entries as i64)));
+            }
+            let shape = if id == td.root_id { "doubleoctagon" } else { "box" };
+            DrawingVertex { id, label, shape, colour: NEUTRAL_COLOUR, data }
+        }).collect_vec();
+        let edges = td.iter_e().map(|x| (x.id1, x.id2)).sorted().map(|(id1, id2)|
+            DrawingEdge { source: id1, target: id2, label: None, colour: NEUTRAL_COLOUR, dashed: false, data: Vec::new() }
+        ).collect_vec();
+        Drawing { name: "tree_decomposition", directed: true, vertices, edges }
+    }
+
+    pub fn to_dot(&self) -> String {
+        let (keyword, connector) = if self.directed { ("digraph", "->") } else { ("graph", "--") };
+        let mut answer = format!("{} {} {{\n    node [style=filled, fontname=\"Helvetica\"];\n", keyword, self.name);
+        for vertex in self.vertices.iter() {
+            answer += &format!(
+                "    {} [label=\"{}\", shape={}, fillcolor=\"{}\"];\n",
+                vertex.id, dot_escape(&vertex.label), vertex.shape, hex_colour(vertex.colour)
+            );
+        }
+        for edge in self.edges.iter() {
+            let mut attributes = vec![format!("color=\"{}\"", hex_colour(edge.colour))];
+            if edge.dashed {
+                attributes.push("style=dashed".to_string());
+            }
+            if let Some(label) = &edge.label {
+                attributes.push(format!("label=\"{}\"", dot_escape(label)));
+            }
+            answer += &format!("    {} {} {} [{}];\n", edge.source, connector, edge.target, attributes.join(", "));
+        }
+        answer += "}\n";
+        answer
+    }
+
+    // Colours are stored in the r, g and b attributes recognised by Gephi
+    pub fn to_graphml(&self) -> String {
+        let mut answer = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n"
+        );
+        for (domain, keys) in [("node", self.vertex_keys()), ("edge", self.edge_keys())] {
+            for (name, graphml_type) in keys {
+                answer += &format!("  <key id=\"{0}_{1}\" for=\"{0}\" attr.name=\"{1}\" attr.type=\"{2}\"/>\n", domain, name, graphml_type);
+            }
+        }
+        answer += &format!("  <graph id=\"{}\" edgedefault=\"{}\">\n", self.name, if self.directed { "directed" } else { "undirected" });
+        for vertex in self.vertices.iter() {
+            answer += &format!("    <node id=\"n{}\">\n", vertex.id);
+            answer += &graphml_data("node", "label", &DrawingValue::Text(vertex.label.clone()));
+            answer += &graphml_colour("node", vertex.colour);
+            for (name, value) in vertex.data.iter() {
+                answer += &graphml_data("node", name, value);
+            }
+            answer += "    </node>\n";
+        }
+        for (edge_i, edge) in self.edges.iter().enumerate() {
+            answer += &format!("    <edge id=\"e{}\" source=\"n{}\" target=\"n{}\">\n", edge_i, edge.source, edge.target);
+            if let Some(label) = &edge.label {
+                answer += &graphml_data("edge", "label", &DrawingValue::Text(label.clone()));
+            }
+            answer += &graphml_colour("edge", edge.colour);
+            for (name, value) in edge.data.iter() {
+                answer += &graphml_data("edge", name, value);
+            }
+            answer += "    </edge>\n";
+        }
+        answer += "  </graph>\n</graphml>\n";
+        answer
+    }
+
+    pub fn write(&self, path: &str, format: VisualisationFormat) -> io::Result<()> {
+        fs::write(path, match format {
+            VisualisationFormat::DOT => self.to_dot(),
+            VisualisationFormat::GraphML => self.to_graphml(),
+        })
+    }
+
+    fn vertex_keys(&self) -> Vec<(&'static str, &'static str)> {
+        let data_keys = self.vertices.iter().flat_map(|x| x.data.iter().map(|(name, value)| (*name, value.graphml_type())));
+        [("label", "string"), ("r", "int"), ("g", "int"), ("b", "int")].into_iter().chain(data_keys).unique_by(|x| x.0).collect()
+    }
+
+    fn edge_keys(&self) -> Vec<(&'static str, &'static str)> {
+        let data_keys = self.edges.iter().flat_map(|x| x.data.iter().map(|(name, value)| (*name, value.graphml_type())));
+        [("label", "string"), ("r", "int"), ("g", "int"), ("b", "int")].into_iter().chain(data_keys).unique_by(|x| x.0).collect()
+    }
+}
+
+
+
+fn primary_substation_colours(grid: &SwitchSelectionGraph) -> HashMap<usize, (u8, u8, u8)> {
+    grid.iter_v()
+        .filter(|x| grid.v_attrs(x).unwrap().tap_position.is_some())
+        .sorted()
+        .enumerate()
+        .map(|(i, x)| (x, PALETTE[i % PALETTE.len()]))
+        .collect()
+}
+
+// Breadth-first search from all primary substations at once that doesn't pass
+// opened switches. Maps each reached substation to the primary substation  it
+// was reached from.
+fn feeding_primary_substations(grid: &SwitchSelectionGraph) -> HashMap<usize, usize> {
+    let mut answer: HashMap<usize, usize> = HashMap::new();
+    let mut queue: VecDeque<usize> = VecDeque::new();
+    for primary_substation_id in grid.iter_v().filter(|x| grid.v_attrs(x).unwrap().tap_position.is_some()).sorted() {
+        answer.insert(primary_substation_id, primary_substation_id);
+        queue.push_back(primary_substation_id);
+    }
+    while let Some(curr_id) = queue.pop_front() {
+        let feeder = answer[&curr_id];
+        for adjacent_id in grid.iter_adjacent(&curr_id).unwrap().sorted() {
+            if answer.contains_key(&adjacent_id) || grid.e_attrs(&curr_id, &adjacent_id, &0).unwrap().switch {
+                continue;
+            }
+            answer.insert(adjacent_id, feeder);
+            queue.push_back(adjacent_id);
+        }
+    }
+    answer
+}
+
+#[inline]
+fn hex_colour(colour: (u8, u8, u8)) -> String {
+    format!("#{:02x}{:02x}{:02x}", colour.0, colour.1, colour.2)
+}
+
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn graphml_data(domain: &str, name: &str, value: &DrawingValue) -> String {
+    format!("      <data key=\"{}_{}\">{}</data>\n", domain, name, xml_escape(&value.to_plain_string()))
+}
+
+fn graphml_colour(domain: &str, colour: (u8, u8, u8)) -> String {
+    graphml_data(domain, "r", &DrawingValue::Int(colour.0 as i64))
+        + &graphml_data(domain, "g", &DrawingValue::Int(colour.1 as i64))
+        + &graphml_data(domain, "b", &DrawingValue::Int(colour.2 as i64))
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use crabnets::{BasicImmutableGraph, ImmutableGraphContainer};
+    use itertools::Itertools;
+    use crate::{formats, solver::{base_solver::BaseSolver, brute_force_solver::BruteForceSolver}, switch_selection_instance::{SwitchSelectionGraph, SwitchSelectionInstance}, tree_decomposition::TreeDecomposition};
+    use super::{dot_escape, feeding_primary_substations, xml_escape, Drawing, VisualisationFormat};
+
+    const FIXTURES: [&str; 5] = ["single_line", "parallel_feeders", "long_chain", "branching_secondary", "secondary_cycle"];
+
+    // Grid of a fixture with the optimal solution rendered onto it
+    fn solved_grid(name: &str) -> SwitchSelectionGraph {
+        let mut grid = formats::read_gnbs(&format!("{}/tests/fixtures/{}.gnbs", env!("CARGO_MANIFEST_DIR"), name)).unwrap();
+        let mut solver = BruteForceSolver::with_input(SwitchSelectionInstance::new(grid.clone()).unwrap()).unwrap();
+        solver.solve().unwrap();
+        solver.get_solution().unwrap().render(&mut grid);
+        grid
+    }
+
+    // (id, attr.type) of each <key> element and (key, value) of each <data> element
+    fn graphml_keys_and_data(graphml: &str) -> (Vec<(String, String)>, Vec<(String, String)>) {
+        let keys = graphml.split("<key id=\"").skip(1).map(|x| {
+            let graphml_type = x.split("attr.type=\"").nth(1).unwrap().split('"').next().unwrap();
+            (x.split('"').next().unwrap().to_string(), graphml_type.to_string())
+        }).collect();
+        let data = graphml.split("<data key=\"").skip(1).map(|x| {
+            let (key, rest) = x.split_once("\">").unwrap();
+            (key.to_string(), rest.split("</data>").next().unwrap().to_string())
+        }).collect();
+        (keys, data)
+    }
+
+    #[test]
+    fn labels_are_escaped() {
+        assert_eq!(dot_escape("bag 1\n{\"a\", \\}"), "bag 1\\n{\\\"a\\\", \\\\}");
+        assert_eq!(xml_escape("<a title=\"x\">\n&</a>"), "&lt;a title=&quot;x&quot;&gt;\n&amp;&lt;/a&gt;");
+    }
+
+    #[test]
+    fn graphml_keys_are_unique_and_match_the_values() {
+        for name in FIXTURES {
+            let grid = solved_grid(name);
+            let dg_kernel = SwitchSelectionInstance::new(grid.clone()).unwrap().dg_kernel_for_switch_selection();
+            let td = TreeDecomposition::for_switch_selection_graph(&dg_kernel).unwrap();
+            let drawings = [Drawing::for_grid(&grid), Drawing::for_dg_kernel(&dg_kernel, &grid), Drawing::for_tree_decomposition(&td.to_nice(), &[])];
+            for drawing in drawings {
+                let (keys, data) = graphml_keys_and_data(&drawing.to_graphml());
+                assert_eq!(keys.iter().map(|x| &x.0).unique().count(), keys.len(), "{}, {}", name, drawing.name);
+                let key_types: HashMap<String, String> = keys.into_iter().collect();
+                for (key, value) in data {
+                    let matches = match key_types[&key].as_str() {
+                        "boolean" => value == "true" || value == "false",
+                        "int" => value.parse::<i32>().is_ok(),
+                        "long" => value.parse::<i64>().is_ok(),
+                        "double" => value.parse::<f64>().is_ok(),
+                        "string" => true,
+                        _ => false,
+                    };
+                    assert!(matches, "{}, {}: {} = {}", name, drawing.name, key, value);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn each_substation_is_fed_by_one_primary_substation() {
+        for name in FIXTURES {
+            let grid = solved_grid(name);
+            let feeders = feeding_primary_substations(&grid);
+            assert_eq!(feeders.keys().copied().sorted().collect_vec(), grid.iter_v().sorted().collect_vec(), "{}", name);
+            for primary_substation_id in grid.iter_v().filter(|x| grid.v_attrs(x).unwrap().tap_position.is_some()) {
+                assert_eq!(feeders[&primary_substation_id], primary_substation_id, "{}", name);
+            }
+            // Substations connected by a closed switch have the same feeder, so no
+            // two primary substations share a component
+            for edge in grid.iter_e().filter(|x| !grid.e_attrs(&x.id1, &x.id2, &0).unwrap().switch) {
+                assert_eq!(feeders[&edge.id1], feeders[&edge.id2], "{}: {{{}, {}}}", name, edge.id1, edge.id2);
+            }
+        }
+    }
+
+    #[test]
+    fn formats_are_deduced_from_extensions() {
+        assert_eq!(VisualisationFormat::from_path("grid.dot").unwrap(), VisualisationFormat::DOT);
+        assert_eq!(VisualisationFormat::from_path("grid.gv").unwrap(), VisualisationFormat::DOT);
+        assert_eq!(VisualisationFormat::from_path("grid.graphml").unwrap(), VisualisationFormat::GraphML);
+        for path in ["grid.png", "grid", "dot", "grid.dot.txt"] {
+            assert!(VisualisationFormat::from_path(path).is_err(), "{}", path);
+        }
+    }
+}